@@ -0,0 +1,317 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+
+#[derive(Clone)]
+pub struct Cached {
+    pub bytes: Arc<Vec<u8>>,
+    pub decoded: Option<Arc<DynamicImage>>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    pub bytes: usize,
+    pub max_bytes: usize,
+    pub urls: Vec<String>,
+}
+
+struct Entry {
+    cached: Cached,
+    size: usize,
+    inserted: Instant,
+    // position in the recency order, higher is more recent
+    tick: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+}
+
+impl Inner {
+    fn remove(&mut self, url: &str) -> Option<Entry> {
+        let entry = self.entries.remove(url)?;
+        self.recency.remove(&entry.tick);
+        self.bytes -= entry.size;
+        Some(entry)
+    }
+
+    fn touch(&mut self, url: &str) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(url) {
+            self.recency.remove(&entry.tick);
+            entry.tick = tick;
+            self.recency.insert(tick, url.to_string());
+        }
+    }
+}
+
+// downloaded images bounded by total size, evicting least recently used and expired entries
+pub struct ImageCache {
+    inner: Mutex<Inner>,
+    max_bytes: usize,
+    ttl: Duration,
+    keep_decoded: bool,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl ImageCache {
+    pub fn new(max_bytes: usize, ttl: Duration, keep_decoded: bool) -> ImageCache {
+        ImageCache {
+            inner: Mutex::new(Inner::default()),
+            max_bytes,
+            ttl,
+            keep_decoded,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, url: &str) -> Option<Cached> {
+        let mut inner = self.inner.lock().unwrap();
+        let expired = match inner.entries.get(url) {
+            Some(entry) => entry.inserted.elapsed() >= self.ttl,
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+        if expired {
+            inner.remove(url);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        inner.touch(url);
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        inner.entries.get(url).map(|entry| entry.cached.clone())
+    }
+
+    // stores the image, the decoded copy is kept only when the cache is configured to
+    pub fn insert(&self, url: &str, bytes: Vec<u8>, decoded: Option<DynamicImage>) {
+        let decoded = decoded.filter(|_| self.keep_decoded).map(Arc::new);
+        let size = bytes.len() + decoded.as_ref().map_or(0, |image| image.as_bytes().len());
+        if size > self.max_bytes {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(url);
+        self.evict_expired(&mut inner);
+        while inner.bytes + size > self.max_bytes {
+            let oldest = match inner.recency.values().next() {
+                Some(url) => url.clone(),
+                None => break,
+            };
+            inner.remove(&oldest);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let cached = Cached {
+            bytes: Arc::new(bytes),
+            decoded,
+        };
+        inner.entries.insert(
+            url.to_string(),
+            Entry {
+                cached,
+                size,
+                inserted: Instant::now(),
+                tick: 0,
+            },
+        );
+        inner.bytes += size;
+        inner.touch(url);
+    }
+
+    fn evict_expired(&self, inner: &mut Inner) {
+        let expired = inner
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.inserted.elapsed() >= self.ttl)
+            .map(|(url, _)| url.clone())
+            .collect::<Vec<String>>();
+        for url in expired {
+            inner.remove(&url);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: inner.entries.len(),
+            bytes: inner.bytes,
+            max_bytes: self.max_bytes,
+            urls: inner.recency.values().rev().cloned().collect(),
+        }
+    }
+}
+
+// on-disk second tier that survives restarts, files are named by the URL hash
+pub struct DiskCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl DiskCache {
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: u64) -> DiskCache {
+        DiskCache {
+            dir: dir.into(),
+            max_bytes,
+        }
+    }
+
+    pub fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(hex::encode(Sha256::digest(url.as_bytes())))
+    }
+
+    pub async fn get(&self, url: &str) -> Option<Vec<u8>> {
+        let path = self.path(url);
+        let bytes = tokio::fs::read(&path).await.ok()?;
+        // the modification time doubles as the last access time for eviction
+        let _ = tokio::task::spawn_blocking(move || touch(&path)).await;
+        Some(bytes)
+    }
+
+    pub async fn remove(&self, url: &str) {
+        let _ = tokio::fs::remove_file(self.path(url)).await;
+    }
+
+    pub async fn insert(&self, url: &str, bytes: &[u8]) -> io::Result<()> {
+        if bytes.len() as u64 > self.max_bytes {
+            return Ok(());
+        }
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        // write to a temporary name first so readers never see a partial file
+        let path = self.path(url);
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, bytes).await?;
+        tokio::fs::rename(&partial, &path).await?;
+
+        let dir = self.dir.clone();
+        let max_bytes = self.max_bytes;
+        tokio::task::spawn_blocking(move || evict(&dir, max_bytes))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+// shares one running load between concurrent callers asking for the same key
+pub struct InFlight<T> {
+    calls: Mutex<HashMap<String, Arc<OnceCell<T>>>>,
+}
+
+impl<T> Default for InFlight<T> {
+    fn default() -> Self {
+        InFlight {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> InFlight<T> {
+    // runs load unless a call for key is already running, in which case its result is awaited
+    pub async fn run<F, Fut>(&self, key: &str, load: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let call = self
+            .calls
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        // if the leading caller is dropped mid-load a waiter takes over
+        let value = call.get_or_init(load).await.clone();
+
+        let mut calls = self.calls.lock().unwrap();
+        if calls
+            .get(key)
+            .is_some_and(|running| Arc::ptr_eq(running, &call))
+        {
+            calls.remove(key);
+        }
+        value
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn touch(path: &Path) -> io::Result<()> {
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+// removes the least recently used cache files until they fit max_bytes, anything else in
+// the directory, partial writes included, is neither counted nor touched
+fn evict(dir: &Path, max_bytes: u64) -> io::Result<()> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !is_cache_file(&entry.file_name()) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            files.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+    }
+
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    files.sort();
+    for (_, len, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+        fs::remove_file(path)?;
+        total -= len;
+    }
+    Ok(())
+}
+
+// names written by DiskCache::path, the hex SHA-256 of a URL
+fn is_cache_file(name: &std::ffi::OsStr) -> bool {
+    name.to_str()
+        .is_some_and(|name| name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit()))
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
@@ -9,8 +11,11 @@ pub enum AppError {
     #[error(transparent)]
     ValidationError(#[from] validator::ValidationErrors),
 
-    #[error("font not found")]
-    FontNotFound,
+    #[error("font {name:?} not found, closest matches: [{}]", .suggestions.join(", "))]
+    FontNotFound {
+        name: String,
+        suggestions: Vec<String>,
+    },
 
     #[error(transparent)]
     Unknown(#[from] anyhow::Error),
@@ -29,10 +34,42 @@ pub enum AppError {
 
     #[error("Timeout")]
     Timeout,
+
+    #[error("batch can hold at most {0} covers")]
+    BatchTooLarge(usize),
+
+    #[error("job not found")]
+    JobNotFound,
+
+    #[error("at most {0} jobs can wait or run at once")]
+    TooManyJobs(usize),
+
+    #[error("{0}")]
+    GenerationFailed(String),
+
+    #[error("{0} is not allowed")]
+    UrlNotAllowed(String),
+
+    #[error("image is larger than {0} bytes")]
+    ImageTooLarge(usize),
+
+    #[error("{0} is not a supported image")]
+    UnsupportedImage(String),
+
+    #[error("{0}")]
+    FetchFailed(String),
+
+    #[error("{0}")]
+    InvalidUpload(String),
+
+    // an error from a download shared by several requests
+    #[error(transparent)]
+    Shared(#[from] Arc<AppError>),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
+impl AppError {
+    // status code and message sent back to the client
+    pub fn parts(&self) -> (StatusCode, String) {
         match self {
             AppError::ValidationError(_) => {
                 let message = format!("Input validation error: [{}]", self).replace('\n', ", ");
@@ -52,7 +89,7 @@ impl IntoResponse for AppError {
             }
             AppError::Timeout => {
                 let message = format!("Timeout Error: [{}]", self).replace('\n', ", ");
-                (StatusCode::INTERNAL_SERVER_ERROR, message)
+                (StatusCode::GATEWAY_TIMEOUT, message)
             }
             AppError::ImageError(_) => {
                 let message = format!("Image Error: [{}]", self).replace('\n', ", ");
@@ -62,11 +99,53 @@ impl IntoResponse for AppError {
                 let message = format!("Std IO Error: [{}]", self).replace('\n', ", ");
                 (StatusCode::INTERNAL_SERVER_ERROR, message)
             }
-            AppError::FontNotFound => {
+            AppError::FontNotFound { .. } => {
                 let message = format!("Font not found: [{}]", self).replace('\n', ", ");
-                (StatusCode::INTERNAL_SERVER_ERROR, message)
+                (StatusCode::NOT_FOUND, message)
             }
+            AppError::BatchTooLarge(_) => {
+                let message = format!("Batch too large: [{}]", self).replace('\n', ", ");
+                (StatusCode::PAYLOAD_TOO_LARGE, message)
+            }
+            AppError::JobNotFound => {
+                let message = format!("Job not found: [{}]", self).replace('\n', ", ");
+                (StatusCode::NOT_FOUND, message)
+            }
+            AppError::TooManyJobs(_) => {
+                let message = format!("Too many jobs: [{}]", self).replace('\n', ", ");
+                (StatusCode::TOO_MANY_REQUESTS, message)
+            }
+            AppError::GenerationFailed(_) => {
+                let message = format!("Generation failed: [{}]", self).replace('\n', ", ");
+                (StatusCode::BAD_GATEWAY, message)
+            }
+            AppError::UrlNotAllowed(_) => {
+                let message = format!("Image URL rejected: [{}]", self).replace('\n', ", ");
+                (StatusCode::FORBIDDEN, message)
+            }
+            AppError::ImageTooLarge(_) => {
+                let message = format!("Image too large: [{}]", self).replace('\n', ", ");
+                (StatusCode::PAYLOAD_TOO_LARGE, message)
+            }
+            AppError::UnsupportedImage(_) => {
+                let message = format!("Unsupported image: [{}]", self).replace('\n', ", ");
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, message)
+            }
+            AppError::FetchFailed(_) => {
+                let message = format!("Image download failed: [{}]", self).replace('\n', ", ");
+                (StatusCode::BAD_GATEWAY, message)
+            }
+            AppError::InvalidUpload(_) => {
+                let message = format!("Invalid upload: [{}]", self).replace('\n', ", ");
+                (StatusCode::BAD_REQUEST, message)
+            }
+            AppError::Shared(inner) => inner.parts(),
         }
-        .into_response()
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        self.parts().into_response()
     }
 }
@@ -0,0 +1,208 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use reqwest::{header, redirect::Policy, Client, ClientBuilder, Response, Url};
+
+use crate::{error::AppError, settings::Settings};
+
+const MAX_REDIRECTS: usize = 5;
+
+// downloads a user supplied image URL, refusing anything that is not a public image
+pub async fn fetch_image(url: &str, settings: &Settings) -> Result<Vec<u8>, AppError> {
+    let mut url = Url::parse(url).map_err(|e| AppError::UrlNotAllowed(e.to_string()))?;
+
+    // redirects are followed by hand so every hop goes through the same checks
+    for _ in 0..=MAX_REDIRECTS {
+        let client = pinned_client(&url, &settings.fetch_allowed_hosts, settings)
+            .await?
+            .build()?;
+        let response = within(settings.fetch_read_timeout, client.get(url.clone()).send()).await?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| AppError::FetchFailed("redirect without location".to_string()))?;
+            url = url
+                .join(location)
+                .map_err(|e| AppError::UrlNotAllowed(e.to_string()))?;
+            continue;
+        }
+        if !response.status().is_success() {
+            return Err(AppError::FetchFailed(format!(
+                "image server answered {}",
+                response.status()
+            )));
+        }
+        return read_image(response, settings).await;
+    }
+    Err(AppError::FetchFailed("too many redirects".to_string()))
+}
+
+// checks the URL and prepares a client that can only connect to the vetted addresses
+pub async fn pinned_client(
+    url: &Url,
+    allowed_hosts: &[String],
+    settings: &Settings,
+) -> Result<ClientBuilder, AppError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::UrlNotAllowed(format!("scheme {}", url.scheme())));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| AppError::UrlNotAllowed("missing host".to_string()))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if !host_allowed(host, allowed_hosts) {
+        return Err(AppError::UrlNotAllowed(format!("host {}", host)));
+    }
+
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => within(settings.fetch_connect_timeout, async {
+            tokio::net::lookup_host((host, port)).await
+        })
+        .await?
+        .collect(),
+    };
+    if addrs.is_empty() {
+        return Err(AppError::FetchFailed(format!("{} did not resolve", host)));
+    }
+    if !settings.fetch_allow_private {
+        if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+            return Err(AppError::UrlNotAllowed(format!("address {}", addr.ip())));
+        }
+    }
+
+    // the proxy would resolve the name again, so it is bypassed
+    Ok(Client::builder()
+        .redirect(Policy::none())
+        .no_proxy()
+        .connect_timeout(settings.fetch_connect_timeout)
+        .resolve_to_addrs(host, &addrs))
+}
+
+// streams the body while enforcing the size cap, then checks it really is an image
+async fn read_image(mut response: Response, settings: &Settings) -> Result<Vec<u8>, AppError> {
+    let max_bytes = settings.fetch_max_bytes;
+    if let Some(content_type) = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        if !mime.starts_with("image/") && mime != "application/octet-stream" {
+            return Err(AppError::UnsupportedImage(format!("content type {}", mime)));
+        }
+    }
+    if response.content_length().unwrap_or(0) > max_bytes as u64 {
+        return Err(AppError::ImageTooLarge(max_bytes));
+    }
+
+    // each chunk has its own deadline and the whole body another, so a trickle cannot last
+    let bytes = within(settings.fetch_body_timeout, async {
+        let mut bytes = Vec::new();
+        while let Some(chunk) = within(settings.fetch_read_timeout, response.chunk()).await? {
+            if bytes.len() + chunk.len() > max_bytes {
+                return Err(AppError::ImageTooLarge(max_bytes));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(bytes)
+    })
+    .await?;
+
+    image::guess_format(&bytes)
+        .map_err(|_| AppError::UnsupportedImage("unknown file signature".to_string()))?;
+    Ok(bytes)
+}
+
+// runs a network step under a deadline, reporting timeouts of either kind as AppError::Timeout
+async fn within<T, E>(
+    limit: Duration,
+    step: impl std::future::Future<Output = Result<T, E>>,
+) -> Result<T, AppError>
+where
+    AppError: From<E>,
+{
+    match tokio::time::timeout(limit, step).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => match AppError::from(e) {
+            AppError::ReqwestError(e) if e.is_timeout() => Err(AppError::Timeout),
+            e => Err(e),
+        },
+        Err(_) => Err(AppError::Timeout),
+    }
+}
+
+// an empty list allows every host, entries also match their subdomains
+pub fn host_allowed(host: &str, allowed: &[String]) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    allowed.is_empty()
+        || allowed.iter().any(|entry| {
+            let entry = entry.trim_end_matches('.').to_ascii_lowercase();
+            host == entry || host.ends_with(&format!(".{}", entry))
+        })
+}
+
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match embedded_v4(ip) {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+// v6 addresses that reach a v4 host, which has to pass the v4 checks instead
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let [a, b, c, d, e, f, g, h] = ip.segments();
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return Some(ip);
+    }
+    // nat64 well known prefix 64:ff9b::/96 keeps the host in the last 32 bits
+    if [a, b, c, d, e, f] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        return Some(Ipv4Addr::from(((g as u32) << 16) | h as u32));
+    }
+    // 6to4 2002::/16 keeps it in the 32 bits after the prefix
+    if a == 0x2002 {
+        return Some(Ipv4Addr::from(((b as u32) << 16) | c as u32));
+    }
+    None
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // carrier grade nat
+        || (a == 100 && (64..128).contains(&b))
+        // protocol assignments and benchmarking
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        // reserved
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // unique local
+        || (first & 0xfe00) == 0xfc00
+        // link local
+        || (first & 0xffc0) == 0xfe80
+        // documentation
+        || first == 0x2001 && ip.segments()[1] == 0x0db8)
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{
+    error::AppError,
+    fonts::{render_preview, FontInfo},
+    overlay::{
+        chain::FontChain,
+        output::{encode, Encoded, OutputFormat},
+    },
+    router::AppState,
+};
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct PreviewParams {
+    #[serde(default = "default_preview_text")]
+    #[validate(length(min = 1, max = 200))]
+    pub text: String,
+    #[serde(default = "default_preview_size")]
+    #[validate(range(min = 8.0, max = 256.0))]
+    pub size: f32,
+}
+
+fn default_preview_text() -> String {
+    "The quick brown fox Съешь же ещё".to_string()
+}
+
+fn default_preview_size() -> f32 {
+    48.0
+}
+
+#[axum_macros::debug_handler]
+pub async fn list_fonts(State(state): State<Arc<AppState>>) -> Json<Vec<FontInfo>> {
+    Json(state.fonts.list())
+}
+
+#[axum_macros::debug_handler]
+pub async fn font_preview(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<PreviewParams>,
+) -> Result<Encoded, AppError> {
+    params.validate()?;
+    let font = FontChain::single(state.fonts.find(&id)?.font.clone());
+
+    let bytes = tokio::task::spawn_blocking(move || {
+        let image = render_preview(&font, &params.text, params.size);
+        encode(&image, OutputFormat::Png, 100)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("preview task failed: {}", e))??;
+    Ok(Encoded {
+        bytes,
+        format: OutputFormat::Png,
+        negotiated: false,
+    })
+}
@@ -0,0 +1,154 @@
+pub mod handlers;
+pub mod registry;
+
+use std::path::Path;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use rusttype::{point, Scale};
+use rustybuzz::ttf_parser::{name_id, Face, PlatformId};
+use serde::{Deserialize, Serialize};
+use validator::ValidationError;
+
+use crate::overlay::{chain::FontChain, helpers::calc_text_width};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Script {
+    Latin,
+    Cyrillic,
+}
+
+impl Script {
+    const ALL: [Script; 2] = [Script::Latin, Script::Cyrillic];
+
+    // letters a font must draw to count as covering the script, only capitals are
+    // required since several display fonts are caps only and covers are mostly uppercase
+    fn letters(&self) -> Box<dyn Iterator<Item = char>> {
+        match self {
+            Script::Latin => Box::new('A'..='Z'),
+            Script::Cyrillic => Box::new(('А'..='Я').chain(['Ё'])),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FontInfo {
+    // stable id accepted by author_font and title_font
+    pub id: String,
+    // file name in the fonts directory, also accepted as an alias of the id
+    pub file: String,
+    pub family: String,
+    pub style: String,
+    pub scripts: Vec<Script>,
+}
+
+impl FontInfo {
+    // reads the metadata of a font file, the id is assigned by the registry
+    pub fn from_data(file: &str, data: &[u8]) -> Option<FontInfo> {
+        let face = Face::parse(data, 0).ok()?;
+        let stem = Path::new(file)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| file.to_string());
+        let family = name(&face, name_id::TYPOGRAPHIC_FAMILY)
+            .or_else(|| name(&face, name_id::FAMILY))
+            .unwrap_or(stem);
+        let style = name(&face, name_id::TYPOGRAPHIC_SUBFAMILY)
+            .or_else(|| name(&face, name_id::SUBFAMILY))
+            .unwrap_or_else(|| "Regular".to_string());
+        let scripts = Script::ALL
+            .into_iter()
+            .filter(|script| script.letters().all(|c| has_glyph(&face, c)))
+            .collect();
+        Some(FontInfo {
+            id: file.to_string(),
+            file: file.to_string(),
+            family,
+            style,
+            scripts,
+        })
+    }
+}
+
+// some fonts map letters they do not draw to empty or .notdef glyphs, only spaces may be empty
+// and control characters are never drawn at all
+pub fn has_glyph(face: &Face, c: char) -> bool {
+    c.is_control()
+        || face.glyph_index(c).is_some_and(|id| {
+            id.0 != 0 && (c.is_whitespace() || face.glyph_bounding_box(id).is_some())
+        })
+}
+
+// reads a name record, preferring unicode entries over legacy mac roman ones
+fn name(face: &Face, id: u16) -> Option<String> {
+    let records = face
+        .names()
+        .into_iter()
+        .filter(|record| record.name_id == id);
+    let mut legacy = None;
+    for record in records {
+        if let Some(name) = record.to_string() {
+            return Some(name).filter(|name| !name.trim().is_empty());
+        }
+        if record.platform_id == PlatformId::Macintosh && legacy.is_none() {
+            legacy = Some(record.name.iter().map(|b| *b as char).collect::<String>());
+        }
+    }
+    legacy.filter(|name| !name.trim().is_empty())
+}
+
+// font names are plain file names or ids, never paths
+pub fn validate_font_name(name: &str) -> Result<(), ValidationError> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | ' ');
+    let valid = !name.is_empty()
+        && name.len() <= 128
+        && name.chars().all(allowed)
+        && !name.starts_with('.')
+        && !name.contains("..");
+    match valid {
+        true => Ok(()),
+        false => Err(ValidationError::new("font_name")),
+    }
+}
+
+// number of single character edits turning a into b
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut row = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != *cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+// draws the text in black on a white strip sized to fit it
+pub fn render_preview(font: &FontChain, text: &str, size: f32) -> DynamicImage {
+    let scale = Scale::uniform(size);
+    let v_metrics = font.v_metrics(scale);
+    let padding = (size / 4.0).ceil();
+    let width = calc_text_width(text, font, scale) + 2 * padding as u32;
+    let height = (v_metrics.ascent - v_metrics.descent + 2.0 * padding).ceil() as u32;
+
+    let mut image = RgbaImage::from_pixel(width.max(1), height.max(1), Rgba([255, 255, 255, 255]));
+    for glyph in font.layout(text, scale, point(padding, padding + v_metrics.ascent)) {
+        if let Some(bounding_box) = glyph.pixel_bounding_box() {
+            glyph.draw(|x, y, v| {
+                let x = x as i32 + bounding_box.min.x;
+                let y = y as i32 + bounding_box.min.y;
+                if x >= 0 && y >= 0 && (x as u32) < image.width() && (y as u32) < image.height() {
+                    let pixel = image.get_pixel_mut(x as u32, y as u32);
+                    let shade = (pixel[0] as f32 * (1.0 - v.min(1.0))) as u8;
+                    *pixel = Rgba([shade, shade, shade, 255]);
+                }
+            });
+        }
+    }
+    DynamicImage::ImageRgba8(image)
+}
@@ -0,0 +1,229 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use crate::{error::AppError, overlay::chain::LoadedFont};
+
+use super::{levenshtein, FontInfo};
+
+const SUGGESTIONS: usize = 3;
+
+pub struct RegisteredFont {
+    pub info: FontInfo,
+    pub font: Arc<LoadedFont>,
+}
+
+// name, size and modification time of every font file, compared to detect changes
+type Stamp = Vec<(String, u64, Option<SystemTime>)>;
+
+#[derive(Default)]
+struct Snapshot {
+    // sorted by id
+    fonts: Vec<Arc<RegisteredFont>>,
+    // ids and file names
+    by_name: HashMap<String, Arc<RegisteredFont>>,
+    stamp: Stamp,
+}
+
+// shortest pause between rescans, so a zero interval does not spin
+const MIN_RELOAD_INTERVAL: Duration = Duration::from_millis(100);
+
+// parsed fonts of the fonts directory, rescanned by watch when its files change
+pub struct FontRegistry {
+    dir: PathBuf,
+    reload_interval: Duration,
+    snapshot: RwLock<Arc<Snapshot>>,
+}
+
+impl FontRegistry {
+    pub fn load(dir: impl Into<PathBuf>, reload_interval: Duration) -> FontRegistry {
+        let registry = FontRegistry {
+            dir: dir.into(),
+            reload_interval,
+            snapshot: RwLock::default(),
+        };
+        if let Err(e) = registry.reload() {
+            println!("Fonts could not be loaded from {:?}: {}", registry.dir, e);
+        }
+        registry
+    }
+
+    // looks a font up by id or by file name
+    pub fn get(&self, name: &str) -> Option<Arc<RegisteredFont>> {
+        self.current().by_name.get(name).cloned()
+    }
+
+    // like get, failing with the closest known names when the font is missing
+    pub fn find(&self, name: &str) -> Result<Arc<RegisteredFont>, AppError> {
+        self.get(name).ok_or_else(|| AppError::FontNotFound {
+            name: name.to_string(),
+            suggestions: self.suggest(name),
+        })
+    }
+
+    // ids or file names closest to name by edit distance, one per font
+    pub fn suggest(&self, name: &str) -> Vec<String> {
+        let name = name.to_lowercase();
+        let snapshot = self.current();
+        let mut candidates = snapshot
+            .by_name
+            .iter()
+            .map(|(alias, font)| (levenshtein(&name, &alias.to_lowercase()), alias, font))
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(b.1)));
+
+        let mut suggestions: Vec<String> = Vec::new();
+        let mut seen: Vec<&str> = Vec::new();
+        for (_, alias, font) in candidates {
+            if suggestions.len() == SUGGESTIONS {
+                break;
+            }
+            if !seen.contains(&font.info.id.as_str()) {
+                seen.push(&font.info.id);
+                suggestions.push(alias.clone());
+            }
+        }
+        suggestions
+    }
+
+    pub fn list(&self) -> Vec<FontInfo> {
+        self.current()
+            .fonts
+            .iter()
+            .map(|font| font.info.clone())
+            .collect()
+    }
+
+    // rescans the directory if a file was added, removed or modified, reporting whether it did
+    pub fn reload(&self) -> io::Result<bool> {
+        let stamp = stamp(&self.dir)?;
+        if self.snapshot.read().unwrap().stamp == stamp {
+            return Ok(false);
+        }
+
+        let previous = self.snapshot.read().unwrap().clone();
+        let snapshot = scan(&self.dir, stamp, &previous)?;
+        *self.snapshot.write().unwrap() = Arc::new(snapshot);
+        Ok(true)
+    }
+
+    // checks the directory once per reload interval on a blocking thread, so requests only
+    // ever read the last snapshot, and stops once the registry is dropped. must be called
+    // from inside a tokio runtime
+    pub fn watch(self: &Arc<Self>) {
+        let registry = Arc::downgrade(self);
+        let interval = self.reload_interval.max(MIN_RELOAD_INTERVAL);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let registry = match registry.upgrade() {
+                    Some(registry) => registry,
+                    None => break,
+                };
+                let dir = registry.dir.clone();
+                match tokio::task::spawn_blocking(move || registry.reload()).await {
+                    Ok(Err(e)) => println!("Fonts could not be reloaded from {:?}: {}", dir, e),
+                    Err(e) => println!("Font reload failed: {}", e),
+                    Ok(Ok(_)) => {}
+                }
+            }
+        });
+    }
+
+    fn current(&self) -> Arc<Snapshot> {
+        self.snapshot.read().unwrap().clone()
+    }
+}
+
+fn is_font(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ttf") || ext.eq_ignore_ascii_case("otf"))
+}
+
+fn stamp(dir: &Path) -> io::Result<Stamp> {
+    let mut stamp = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if let (true, Some(file)) = (is_font(&path), path.file_name().and_then(|f| f.to_str())) {
+            let metadata = entry.metadata()?;
+            stamp.push((file.to_string(), metadata.len(), metadata.modified().ok()));
+        }
+    }
+    stamp.sort();
+    Ok(stamp)
+}
+
+// parses the fonts listed in the stamp, reusing the ones whose file did not change
+fn scan(dir: &Path, stamp: Stamp, previous: &Snapshot) -> io::Result<Snapshot> {
+    let mut fonts: Vec<Arc<RegisteredFont>> = Vec::new();
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    for entry in &stamp {
+        let file = &entry.0;
+        let unchanged = previous.stamp.contains(entry);
+        let parsed = match previous.by_name.get(file).filter(|_| unchanged) {
+            Some(font) => Some((font.info.clone(), font.font.clone())),
+            None => {
+                let data = fs::read(dir.join(file))?;
+                let info = FontInfo::from_data(file, &data);
+                let font = LoadedFont::from_vec(data).map(Arc::new);
+                info.zip(font)
+            }
+        };
+        let (mut info, font) = match parsed {
+            Some(parsed) => parsed,
+            None => {
+                println!("Skipping unreadable font {}", file);
+                continue;
+            }
+        };
+
+        // files are visited in name order so duplicate ids get stable suffixes
+        let base = font_id(&info);
+        let count = ids.entry(base.clone()).or_insert(0);
+        *count += 1;
+        info.id = match *count {
+            1 => base,
+            n => format!("{}-{}", base, n),
+        };
+        fonts.push(Arc::new(RegisteredFont { info, font }));
+    }
+
+    fonts.sort_by(|a, b| a.info.id.cmp(&b.info.id));
+    let mut by_name = HashMap::new();
+    for font in &fonts {
+        by_name.insert(font.info.file.clone(), font.clone());
+        by_name.insert(font.info.id.clone(), font.clone());
+    }
+    Ok(Snapshot {
+        fonts,
+        by_name,
+        stamp,
+    })
+}
+
+// family and style as a slug, so the id survives renaming the file
+pub fn font_id(info: &FontInfo) -> String {
+    let family = match slug(&info.family) {
+        family if family.is_empty() => slug(info.file.rsplit_once('.').map_or(&info.file, |s| s.0)),
+        family => family,
+    };
+    match slug(&info.style) {
+        style if style.is_empty() => family,
+        style => format!("{}-{}", family, style),
+    }
+}
+
+// lowercase ascii words joined by dashes
+fn slug(name: &str) -> String {
+    name.to_ascii_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    error::AppError,
+    fetch::pinned_client,
+    overlay::handlers::{render_batch, BookCoverParams},
+    router::AppState,
+    settings::Settings,
+};
+
+use super::{sign, JobStatus, JobView, SIGNATURE_HEADER};
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct JobParams {
+    #[validate(length(min = 1))]
+    pub covers: Vec<BookCoverParams>,
+    #[serde(default)]
+    #[validate(url)]
+    pub webhook_url: Option<String>,
+}
+
+#[axum_macros::debug_handler]
+pub async fn create_job(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<JobParams>,
+) -> Result<(StatusCode, Json<JobView>), AppError> {
+    payload.validate()?;
+    if payload.covers.len() > state.settings.max_batch_size {
+        return Err(AppError::BatchTooLarge(state.settings.max_batch_size));
+    }
+    state.jobs.prune(state.settings.job_ttl);
+    if state.jobs.pending() >= state.settings.max_queued_jobs {
+        return Err(AppError::TooManyJobs(state.settings.max_queued_jobs));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let view = state.jobs.insert(&id, payload.covers.len());
+    tokio::spawn(run_job(id, payload, state));
+
+    Ok((StatusCode::ACCEPTED, Json(view)))
+}
+
+#[axum_macros::debug_handler]
+pub async fn job_status(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<JobView>, AppError> {
+    state.jobs.get(&id).map(Json).ok_or(AppError::JobNotFound)
+}
+
+// waits for a free job worker, renders the covers and reports the outcome
+async fn run_job(id: String, payload: JobParams, state: Arc<AppState>) {
+    let permit = state.job_workers.acquire().await;
+    state.jobs.set_status(&id, JobStatus::Running);
+
+    let progress_state = state.clone();
+    let progress_id = id.clone();
+    let render = render_batch(payload.covers, state.clone(), move |_| {
+        progress_state.jobs.advance(&progress_id)
+    });
+    // dropping the batch on timeout stops its downloads, covers already drawing finish unseen
+    let result = match tokio::time::timeout(state.settings.job_timeout, render).await {
+        Ok(items) => Ok(items),
+        Err(_) => Err(AppError::Timeout.parts().1),
+    };
+
+    let view = match state.jobs.finish(&id, result) {
+        Some(view) => view,
+        None => return,
+    };
+    // a slow webhook must not hold the worker from the next job
+    drop(permit);
+    if let Some(url) = payload.webhook_url {
+        if let Err(e) = notify(&url, &view, &state.settings).await {
+            println!("Webhook {} failed: {}", url, e);
+        }
+    }
+}
+
+// posts the finished job to its webhook, which goes through the same host checks as image URLs
+async fn notify(url: &str, view: &JobView, settings: &Settings) -> Result<(), AppError> {
+    let url = Url::parse(url).map_err(|e| AppError::UrlNotAllowed(e.to_string()))?;
+    let client = pinned_client(&url, &settings.webhook_allowed_hosts, settings)
+        .await?
+        .timeout(settings.webhook_timeout)
+        .build()?;
+    let body = serde_json::to_vec(view)?;
+    let mut request = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+    if !settings.webhook_secret.is_empty() {
+        request = request.header(SIGNATURE_HEADER, sign(&settings.webhook_secret, &body));
+    }
+    request.body(body).send().await?.error_for_status()?;
+    Ok(())
+}
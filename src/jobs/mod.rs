@@ -0,0 +1,136 @@
+pub mod handlers;
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::overlay::handlers::BatchItem;
+
+pub const SIGNATURE_HEADER: &str = "X-Litcovers-Signature";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct JobView {
+    pub id: String,
+    pub status: JobStatus,
+    // share of finished covers, 0..1
+    pub progress: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Vec<BatchItem>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub struct Job {
+    pub view: JobView,
+    pub total: usize,
+    pub done: usize,
+    pub finished_at: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct JobStore {
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+impl JobStore {
+    pub fn insert(&self, id: &str, total: usize) -> JobView {
+        let view = JobView {
+            id: id.to_string(),
+            status: JobStatus::Queued,
+            progress: 0.0,
+            result: None,
+            error: None,
+        };
+        let job = Job {
+            view: view.clone(),
+            total,
+            done: 0,
+            finished_at: None,
+        };
+        self.jobs.lock().unwrap().insert(id.to_string(), job);
+        view
+    }
+
+    pub fn get(&self, id: &str) -> Option<JobView> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|job| job.view.clone())
+    }
+
+    pub fn set_status(&self, id: &str, status: JobStatus) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(id) {
+            job.view.status = status;
+        }
+    }
+
+    // jobs that are queued or running
+    pub fn pending(&self) -> usize {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| job.finished_at.is_none())
+            .count()
+    }
+
+    // counts one more finished cover, a finished job keeps its progress
+    pub fn advance(&self, id: &str) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(id) {
+            if job.finished_at.is_some() {
+                return;
+            }
+            job.done += 1;
+            job.view.progress = job.done as f32 / job.total.max(1) as f32;
+        }
+    }
+
+    pub fn finish(&self, id: &str, result: Result<Vec<BatchItem>, String>) -> Option<JobView> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(id)?;
+        match result {
+            Ok(items) => {
+                job.view.status = JobStatus::Succeeded;
+                job.view.progress = 1.0;
+                job.view.result = Some(items);
+            }
+            Err(error) => {
+                job.view.status = JobStatus::Failed;
+                job.view.error = Some(error);
+            }
+        }
+        job.finished_at = Some(Instant::now());
+        Some(job.view.clone())
+    }
+
+    // drops finished jobs older than ttl
+    pub fn prune(&self, ttl: Duration) {
+        self.jobs
+            .lock()
+            .unwrap()
+            .retain(|_, job| job.finished_at.is_none_or(|at| at.elapsed() < ttl));
+    }
+}
+
+// hex encoded HMAC-SHA256 of the webhook body
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
@@ -1,15 +1,23 @@
 use std::net::SocketAddr;
 
 use axum::Server;
-use router::app;
+use router::{app_with_state, state_with_settings};
+use settings::get_config;
 
+pub mod cache;
 pub mod error;
+pub mod fetch;
+pub mod fonts;
+pub mod jobs;
 pub mod overlay;
+pub mod replicate;
 pub mod router;
 pub mod settings;
 
 pub async fn run_app(addr: SocketAddr) {
-    let app = app();
+    let state = state_with_settings(get_config());
+    state.fonts.watch();
+    let app = app_with_state(state);
 
     Server::bind(&addr)
         .serve(app.into_make_service())
@@ -0,0 +1,440 @@
+use std::{ops::Deref, sync::Arc};
+
+use rusttype::{point, Font, GlyphId, Point, PositionedGlyph, Scale, VMetrics};
+use unicode_bidi::{BidiInfo, Level};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::image::{Direction, WritingMode};
+use crate::fonts::has_glyph;
+
+// a parsed font that keeps its file bytes for the shaper
+pub struct LoadedFont {
+    font: Font<'static>,
+    data: Vec<u8>,
+}
+
+impl LoadedFont {
+    pub fn from_vec(data: Vec<u8>) -> Option<LoadedFont> {
+        let font = Font::try_from_vec(data.clone())?;
+        Some(LoadedFont { font, data })
+    }
+
+    // parsing only reads the table directory, so a face is built per shaping call
+    fn shaper(&self) -> Option<rustybuzz::Face<'_>> {
+        rustybuzz::Face::from_slice(&self.data, 0)
+    }
+
+    fn covers(&self, c: char) -> bool {
+        self.shaper().is_some_and(|face| has_glyph(&face, c))
+    }
+}
+
+impl Deref for LoadedFont {
+    type Target = Font<'static>;
+
+    fn deref(&self) -> &Font<'static> {
+        &self.font
+    }
+}
+
+// a glyph picked by the shaper, offsets in pixels from the pen start on the baseline,
+// or from the top center of the column for stacked text
+#[derive(Clone, Copy, Debug)]
+pub struct ShapedGlyph {
+    pub font: usize,
+    pub id: GlyphId,
+    pub x: f32,
+    pub y: f32,
+    // set in the emphasis chain, from a *marked* span
+    pub emphasized: bool,
+}
+
+// the chain and size factor *marked* spans are set in
+#[derive(Clone)]
+struct Emphasis {
+    chain: FontChain,
+    size: f32,
+}
+
+// a font followed by fallbacks that draw the characters it has no glyph for
+#[derive(Clone)]
+pub struct FontChain {
+    fonts: Vec<Arc<LoadedFont>>,
+    direction: Direction,
+    writing_mode: WritingMode,
+    emphasis: Option<Box<Emphasis>>,
+}
+
+impl FontChain {
+    pub fn new(primary: Arc<LoadedFont>, fallbacks: Vec<Arc<LoadedFont>>) -> FontChain {
+        let mut fonts = vec![primary];
+        fonts.extend(fallbacks);
+        FontChain {
+            fonts,
+            direction: Direction::Auto,
+            writing_mode: WritingMode::Horizontal,
+            emphasis: None,
+        }
+    }
+
+    // sets the base direction lines are reordered against
+    pub fn with_direction(mut self, direction: Direction) -> FontChain {
+        self.direction = direction;
+        if let Some(emphasis) = self.emphasis.take() {
+            self.emphasis = Some(Box::new(Emphasis {
+                chain: emphasis.chain.with_direction(direction),
+                size: emphasis.size,
+            }));
+        }
+        self
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    // stacked text advances down the column, rotated text is shaped like horizontal text
+    pub fn with_writing_mode(mut self, writing_mode: WritingMode) -> FontChain {
+        self.writing_mode = writing_mode;
+        if let Some(emphasis) = self.emphasis.take() {
+            self.emphasis = Some(Box::new(Emphasis {
+                chain: emphasis.chain.with_writing_mode(writing_mode),
+                size: emphasis.size,
+            }));
+        }
+        self
+    }
+
+    // turns on *markup*, spans between asterisks are set in chain at size times the scale
+    pub fn with_emphasis(mut self, chain: FontChain, size: f32) -> FontChain {
+        let chain = chain
+            .with_direction(self.direction)
+            .with_writing_mode(self.writing_mode);
+        self.emphasis = Some(Box::new(Emphasis { chain, size }));
+        self
+    }
+
+    pub fn marks_emphasis(&self) -> bool {
+        self.emphasis.is_some()
+    }
+
+    pub fn writing_mode(&self) -> WritingMode {
+        self.writing_mode
+    }
+
+    pub fn single(font: Arc<LoadedFont>) -> FontChain {
+        FontChain::new(font, Vec::new())
+    }
+
+    pub fn primary(&self) -> &Font<'static> {
+        &self.fonts[0]
+    }
+
+    // line metrics always come from the primary font so mixed lines keep one baseline grid
+    pub fn v_metrics(&self, scale: Scale) -> VMetrics {
+        self.primary().v_metrics(scale)
+    }
+
+    // index of the first font able to draw every character of a grapheme, the primary font when none can
+    fn pick(&self, grapheme: &str) -> usize {
+        self.fonts
+            .iter()
+            .position(|font| grapheme.chars().all(|c| font.covers(c)))
+            .unwrap_or(0)
+    }
+
+    // characters of text no font of the chain can draw, in order of appearance,
+    // *marked* spans are checked against the emphasis chain
+    pub fn missing(&self, text: &str) -> Vec<char> {
+        let emphasis = match &self.emphasis {
+            Some(emphasis) => emphasis,
+            None => return self.missing_plain(text),
+        };
+        let mut missing = Vec::new();
+        for (span, emphasized) in markup_spans(text) {
+            let chain = match emphasized {
+                true => &emphasis.chain,
+                false => self,
+            };
+            for c in chain.missing_plain(span) {
+                if !missing.contains(&c) {
+                    missing.push(c);
+                }
+            }
+        }
+        missing
+    }
+
+    fn missing_plain(&self, text: &str) -> Vec<char> {
+        let mut missing = Vec::new();
+        for c in text.chars() {
+            if c.is_control() || missing.contains(&c) {
+                continue;
+            }
+            if !self.fonts.iter().any(|font| font.covers(c)) {
+                missing.push(c);
+            }
+        }
+        missing
+    }
+
+    // splits text into pieces set in one font, breaking only between graphemes
+    fn runs<'t>(&self, text: &'t str) -> Vec<(usize, &'t str)> {
+        let mut runs = Vec::new();
+        let mut current: Option<(usize, usize)> = None;
+        for (offset, grapheme) in text.grapheme_indices(true) {
+            let keep = current.is_some_and(|(font, _)| {
+                grapheme.chars().all(char::is_whitespace) && self.fonts[font].covers(' ')
+            });
+            let font = match current {
+                Some((font, _)) if keep => font,
+                _ => self.pick(grapheme),
+            };
+            match current {
+                Some((current_font, _)) if current_font == font => {}
+                Some((current_font, start)) => {
+                    runs.push((current_font, &text[start..offset]));
+                    current = Some((font, offset));
+                }
+                None => current = Some((font, offset)),
+            }
+        }
+        if let Some((font, start)) = current {
+            runs.push((font, &text[start..]));
+        }
+        runs
+    }
+
+    // reorders text into visual runs and shapes them left to right,
+    // returning the glyphs and the total advance in pixels
+    pub fn shape(&self, text: &str, scale: Scale) -> (Vec<ShapedGlyph>, f32) {
+        let text = text.chars().filter(|c| !c.is_control()).collect::<String>();
+        let emphasis = match &self.emphasis {
+            Some(emphasis) => emphasis,
+            None => return self.shape_plain(&text, scale),
+        };
+        let mut spans = markup_spans(&text);
+        if self.direction.resolve(&text).is_rtl() && !self.writing_mode.is_vertical() {
+            spans.reverse();
+        }
+        let mut glyphs = Vec::new();
+        let mut caret = 0.0;
+        for (span, emphasized) in spans {
+            let (shaped, advance) = match emphasized {
+                true => emphasis
+                    .chain
+                    .shape_plain(span, emphasized_scale(scale, emphasis.size)),
+                false => self.shape_plain(span, scale),
+            };
+            for glyph in shaped {
+                let (x, y) = match self.writing_mode {
+                    WritingMode::Stacked => (glyph.x, glyph.y + caret),
+                    _ => (glyph.x + caret, glyph.y),
+                };
+                glyphs.push(ShapedGlyph {
+                    x,
+                    y,
+                    emphasized,
+                    ..glyph
+                });
+            }
+            caret += advance;
+        }
+        (glyphs, caret)
+    }
+
+    fn shape_plain(&self, text: &str, scale: Scale) -> (Vec<ShapedGlyph>, f32) {
+        if self.writing_mode == WritingMode::Stacked {
+            return self.shape_stacked(text, scale);
+        }
+        let level = match self.direction {
+            Direction::Auto => None,
+            Direction::Ltr => Some(Level::ltr()),
+            Direction::Rtl => Some(Level::rtl()),
+        };
+        let bidi = BidiInfo::new(text, level);
+        let mut glyphs = Vec::new();
+        let mut caret = 0.0;
+        for paragraph in &bidi.paragraphs {
+            let (levels, runs) = bidi.visual_runs(paragraph, paragraph.range.clone());
+            for run in runs {
+                let rtl = levels[run.start].is_rtl();
+                let mut pieces = self.runs(&text[run]);
+                if rtl {
+                    pieces.reverse();
+                }
+                for (font, piece) in pieces {
+                    caret = self.shape_run(font, piece, rtl, scale, caret, &mut glyphs);
+                }
+            }
+        }
+        (glyphs, caret)
+    }
+
+    // shapes a piece set in one font and direction, returning the caret after it
+    fn shape_run(
+        &self,
+        index: usize,
+        text: &str,
+        rtl: bool,
+        scale: Scale,
+        mut caret: f32,
+        glyphs: &mut Vec<ShapedGlyph>,
+    ) -> f32 {
+        let font = &self.fonts[index];
+        let (scale_x, scale_y) = units_to_pixels(font, scale);
+        match font.shaper() {
+            // the shaper hands right to left runs back in visual order already
+            Some(face) => {
+                let mut buffer = rustybuzz::UnicodeBuffer::new();
+                buffer.push_str(text);
+                buffer.set_direction(match rtl {
+                    true => rustybuzz::Direction::RightToLeft,
+                    false => rustybuzz::Direction::LeftToRight,
+                });
+                buffer.guess_segment_properties();
+                let shaped = rustybuzz::shape(&face, &[], buffer);
+                for (info, position) in shaped.glyph_infos().iter().zip(shaped.glyph_positions()) {
+                    glyphs.push(ShapedGlyph {
+                        font: index,
+                        id: GlyphId(info.glyph_id as u16),
+                        x: caret + position.x_offset as f32 * scale_x,
+                        y: -position.y_offset as f32 * scale_y,
+                        emphasized: false,
+                    });
+                    caret += position.x_advance as f32 * scale_x;
+                }
+            }
+            // fonts the shaper cannot read are set glyph by glyph
+            None => {
+                let chars = text.chars().collect::<Vec<char>>();
+                let ordered: Box<dyn Iterator<Item = &char>> = match rtl {
+                    true => Box::new(chars.iter().rev()),
+                    false => Box::new(chars.iter()),
+                };
+                for c in ordered {
+                    let glyph = font.glyph(*c).scaled(scale);
+                    glyphs.push(ShapedGlyph {
+                        font: index,
+                        id: glyph.id(),
+                        x: caret,
+                        y: 0.0,
+                        emphasized: false,
+                    });
+                    caret += glyph.h_metrics().advance_width;
+                }
+            }
+        }
+        caret
+    }
+
+    // sets upright glyphs top to bottom, the shaper uses vertical metrics where the font has them
+    fn shape_stacked(&self, text: &str, scale: Scale) -> (Vec<ShapedGlyph>, f32) {
+        let mut glyphs = Vec::new();
+        let mut pen = 0.0;
+        for (index, run) in self.runs(text) {
+            let font = &self.fonts[index];
+            let (scale_x, scale_y) = units_to_pixels(font, scale);
+            match font.shaper() {
+                // offsets move the glyph from the column center to its horizontal origin
+                Some(face) => {
+                    let mut buffer = rustybuzz::UnicodeBuffer::new();
+                    buffer.push_str(run);
+                    buffer.set_direction(rustybuzz::Direction::TopToBottom);
+                    buffer.guess_segment_properties();
+                    let shaped = rustybuzz::shape(&face, &[], buffer);
+                    for (info, position) in
+                        shaped.glyph_infos().iter().zip(shaped.glyph_positions())
+                    {
+                        glyphs.push(ShapedGlyph {
+                            font: index,
+                            id: GlyphId(info.glyph_id as u16),
+                            x: position.x_offset as f32 * scale_x,
+                            y: pen - position.y_offset as f32 * scale_y,
+                            emphasized: false,
+                        });
+                        pen -= position.y_advance as f32 * scale_y;
+                    }
+                }
+                // without the shaper every glyph gets a line height, centered across the column
+                None => {
+                    let v_metrics = font.v_metrics(scale);
+                    for c in run.chars() {
+                        let glyph = font.glyph(c).scaled(scale);
+                        glyphs.push(ShapedGlyph {
+                            font: index,
+                            id: glyph.id(),
+                            x: -glyph.h_metrics().advance_width / 2.0,
+                            y: pen + v_metrics.ascent,
+                            emphasized: false,
+                        });
+                        pen += v_metrics.ascent - v_metrics.descent;
+                    }
+                }
+            }
+        }
+        (glyphs, pen)
+    }
+
+    pub fn advance_width(&self, text: &str, scale: Scale) -> f32 {
+        self.shape(text, scale).1
+    }
+
+    // lays shaped text out on one baseline starting at start,
+    // stacked text hangs from start as the top center of its column
+    pub fn layout(&self, text: &str, scale: Scale, start: Point<f32>) -> Vec<PositionedGlyph<'_>> {
+        self.layout_spans(text, scale, start)
+            .into_iter()
+            .map(|(glyph, _)| glyph)
+            .collect()
+    }
+
+    // like layout, flagging the glyphs of *marked* spans
+    pub fn layout_spans(
+        &self,
+        text: &str,
+        scale: Scale,
+        start: Point<f32>,
+    ) -> Vec<(PositionedGlyph<'_>, bool)> {
+        self.shape(text, scale)
+            .0
+            .into_iter()
+            .map(|glyph| {
+                let (font, scale) = match (&self.emphasis, glyph.emphasized) {
+                    (Some(emphasis), true) => (
+                        &emphasis.chain.fonts[glyph.font],
+                        emphasized_scale(scale, emphasis.size),
+                    ),
+                    _ => (&self.fonts[glyph.font], scale),
+                };
+                let positioned = font
+                    .glyph(glyph.id)
+                    .scaled(scale)
+                    .positioned(point(start.x + glyph.x, start.y + glyph.y));
+                (positioned, glyph.emphasized)
+            })
+            .collect()
+    }
+}
+
+// splits text at asterisks into plain and emphasized spans, an unclosed span runs to the end
+fn markup_spans(text: &str) -> Vec<(&str, bool)> {
+    text.split('*')
+        .enumerate()
+        .filter(|(_, span)| !span.is_empty())
+        .map(|(i, span)| (span, i % 2 == 1))
+        .collect()
+}
+
+fn emphasized_scale(scale: Scale, size: f32) -> Scale {
+    Scale {
+        x: scale.x * size,
+        y: scale.y * size,
+    }
+}
+
+// pixels per font unit across and along the baseline
+fn units_to_pixels(font: &Font, scale: Scale) -> (f32, f32) {
+    let scale_y = font.scale_for_pixel_height(scale.y);
+    (scale_y * scale.x / scale.y, scale_y)
+}
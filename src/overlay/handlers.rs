@@ -1,76 +1,578 @@
-use std::io::Cursor;
 use std::sync::Arc;
-use std::time::Instant;
 
-use crate::overlay::helpers::load_font;
+use crate::fonts::validate_font_name;
 use crate::overlay::image::{Image, OverlayText, PositionType};
 use crate::router::AppState;
-use axum::extract::State;
+use anyhow::anyhow;
+use axum::async_trait;
+use axum::body::Body;
+use axum::extract::multipart::MultipartError;
+use axum::extract::{FromRequest, Multipart, State};
+use axum::http::{header, HeaderMap, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::Json;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use serde::{Deserialize, Serialize};
+use tokio::{sync::OwnedSemaphorePermit, task::JoinSet};
+use validator::{Validate, ValidationError, ValidationErrors};
 
 use crate::error::AppError;
 
-use super::image::BlendMode;
+use super::chain::FontChain;
+use super::image::{BlendMode, Direction, WritingMode};
+use super::output::{encode_with_options, package_renditions, OutputOptions, Packaged, Renditions};
+use super::style::{Fill, TextStyle};
+use super::wrap::{hard_lines, wrap_balanced};
 
-#[derive(Deserialize, Serialize)]
+const MAX_FALLBACK_FONTS: usize = 8;
+// largest stroke width, shadow blur and shadow offset in pixels
+const MAX_EFFECT_PX: f32 = 32.0;
+
+#[derive(Deserialize, Serialize, Validate)]
+#[validate(schema(function = "validate_image_source"))]
 pub struct BookCoverParams {
+    #[validate(custom = "validate_font_name")]
     pub author_font: String,
+    #[validate(length(max = 200))]
     pub author: String,
+    #[validate(custom = "validate_position")]
     pub author_position: PositionType,
+    #[serde(default)]
+    pub author_direction: Direction,
+    #[serde(default)]
+    pub author_writing_mode: WritingMode,
+    #[serde(default)]
+    #[validate(range(min = -360.0, max = 360.0))]
+    pub author_rotation: f32,
+    #[serde(default)]
+    #[validate(custom = "validate_arc_radius")]
+    pub author_arc_radius: Option<f32>,
+    // capitals on or off, unset follows the placement
+    #[serde(default)]
+    pub author_uppercase: Option<bool>,
+    #[validate(custom = "validate_font_name")]
     pub title_font: String,
+    #[validate(length(max = 300))]
     pub title: String,
+    #[validate(custom = "validate_position")]
     pub title_position: PositionType,
+    #[serde(default)]
+    pub title_direction: Direction,
+    #[serde(default)]
+    pub title_writing_mode: WritingMode,
+    #[serde(default)]
+    #[validate(range(min = -360.0, max = 360.0))]
+    pub title_rotation: f32,
+    #[serde(default)]
+    #[validate(custom = "validate_arc_radius")]
+    pub title_arc_radius: Option<f32>,
+    #[serde(default)]
+    pub title_uppercase: Option<bool>,
+    // fonts tried in order for characters the main font cannot draw
+    #[serde(default)]
+    #[validate(custom = "validate_fallback_fonts")]
+    pub fallback_fonts: Vec<String>,
+    #[serde(default)]
+    #[validate(custom = "validate_fallback_fonts")]
+    pub author_fallback_fonts: Option<Vec<String>>,
+    #[serde(default)]
+    #[validate(custom = "validate_fallback_fonts")]
+    pub title_fallback_fonts: Option<Vec<String>>,
     pub blend_mode: BlendMode,
+    #[serde(default)]
+    pub author_blend_mode: Option<BlendMode>,
+    #[serde(default)]
+    pub title_blend_mode: Option<BlendMode>,
     pub alfa: f32,
+    #[serde(default)]
     pub image_url: String,
+    // background sent inline instead of image_url, a data URL prefix is allowed
+    #[serde(default)]
+    pub image_base64: Option<String>,
+    // background sent as the image part of a multipart request
+    #[serde(skip)]
+    pub image_upload: Option<Vec<u8>>,
     pub line_length: u8,
+    // lets titles break inside english and russian words
+    #[serde(default = "default_hyphenate")]
+    pub hyphenate: bool,
+    #[serde(default)]
+    #[validate(custom = "validate_style")]
+    pub author_style: TextStyle,
+    #[serde(default)]
+    #[validate(custom = "validate_style")]
+    pub title_style: TextStyle,
+    #[serde(default)]
+    #[validate]
+    pub output: OutputOptions,
+    #[serde(default)]
+    #[validate]
+    pub renditions: Option<Renditions>,
+    #[serde(default = "default_author_max_height")]
+    #[validate(custom = "validate_max_height")]
+    pub author_max_height: f32,
+    #[serde(default = "default_title_max_height")]
+    #[validate(custom = "validate_max_height")]
+    pub title_max_height: f32,
+}
+
+fn validate_position(position: &PositionType) -> Result<(), ValidationError> {
+    position.validate()
+}
+
+fn validate_fallback_fonts(names: &[String]) -> Result<(), ValidationError> {
+    if names.len() > MAX_FALLBACK_FONTS {
+        return Err(ValidationError::new("too_many_fallback_fonts"));
+    }
+    names.iter().try_for_each(|name| validate_font_name(name))
+}
+
+// a fraction of the image width, tight circles would fold the text over itself
+fn validate_arc_radius(radius: f32) -> Result<(), ValidationError> {
+    if !(0.05..=10.0).contains(&radius.abs()) {
+        return Err(ValidationError::new("arc_radius"));
+    }
+    Ok(())
+}
+
+// a fraction of the image height, above zero
+fn validate_max_height(max_height: f32) -> Result<(), ValidationError> {
+    if !(max_height > 0.0 && max_height <= 1.0) {
+        return Err(ValidationError::new("max_height"));
+    }
+    Ok(())
+}
+
+// outlines and shadows are drawn pixel by pixel around the block, their sizes are kept small
+fn validate_style(style: &TextStyle) -> Result<(), ValidationError> {
+    let small = |px: f32| (0.0..=MAX_EFFECT_PX).contains(&px);
+    if let Some(stroke) = &style.stroke {
+        if !small(stroke.width) {
+            return Err(ValidationError::new("stroke_width"));
+        }
+    }
+    if let Some(shadow) = &style.shadow {
+        if !small(shadow.blur) {
+            return Err(ValidationError::new("shadow_blur"));
+        }
+        let (dx, dy) = shadow.offset;
+        if !small(dx.unsigned_abs() as f32) || !small(dy.unsigned_abs() as f32) {
+            return Err(ValidationError::new("shadow_offset"));
+        }
+    }
+    match &style.emphasis {
+        Some(emphasis) => {
+            if !(0.25..=4.0).contains(&emphasis.size) {
+                return Err(ValidationError::new("emphasis_size"));
+            }
+            emphasis.font.as_deref().map_or(Ok(()), validate_font_name)
+        }
+        None => Ok(()),
+    }
+}
+
+// exactly one background source must be given
+fn validate_image_source(params: &BookCoverParams) -> Result<(), ValidationError> {
+    match image_sources(params) {
+        1 => Ok(()),
+        _ => Err(ValidationError::new("one_image_source")),
+    }
+}
+
+fn image_sources(params: &BookCoverParams) -> usize {
+    [
+        !params.image_url.is_empty(),
+        params.image_base64.is_some(),
+        params.image_upload.is_some(),
+    ]
+    .into_iter()
+    .filter(|given| *given)
+    .count()
+}
+
+fn default_hyphenate() -> bool {
+    true
+}
+
+fn default_author_max_height() -> f32 {
+    0.08
+}
+
+fn default_title_max_height() -> f32 {
+    0.3
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BatchItem {
+    pub index: usize,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    // base64 encoded body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// cover parameters sent as JSON, or as multipart form data with a JSON "params" part
+// and the background in an "image" part
+pub struct CoverRequest(pub BookCoverParams);
+
+#[async_trait]
+impl<S> FromRequest<S, Body> for CoverRequest
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let is_multipart = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("multipart/form-data"));
+        if !is_multipart {
+            let Json(params) = Json::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            return Ok(CoverRequest(params));
+        }
+
+        let mut multipart = Multipart::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let mut params: Option<BookCoverParams> = None;
+        let mut image = None;
+        let invalid = |e: MultipartError| AppError::InvalidUpload(e.to_string()).into_response();
+        while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+            let name = field.name().map(String::from);
+            let bytes = field.bytes().await.map_err(invalid)?;
+            match name.as_deref() {
+                Some("params") => {
+                    let parsed = serde_json::from_slice(&bytes).map_err(|e| {
+                        AppError::InvalidUpload(format!("params part: {}", e)).into_response()
+                    })?;
+                    params = Some(parsed);
+                }
+                Some("image") => image = Some(bytes.to_vec()),
+                _ => {}
+            }
+        }
+
+        let mut params = params.ok_or_else(|| {
+            AppError::InvalidUpload("missing params part".to_string()).into_response()
+        })?;
+        params.image_upload = image;
+        Ok(CoverRequest(params))
+    }
 }
 
 #[axum_macros::debug_handler]
 pub async fn book_cover(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<BookCoverParams>,
-) -> Result<Vec<u8>, AppError> {
-    let url = payload.image_url;
-    let mut image = Image::from_url(url.as_str(), state).await?;
+    headers: HeaderMap,
+    CoverRequest(payload): CoverRequest,
+) -> Result<Packaged, AppError> {
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    render_cover(payload, state, accept, None).await
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GlyphReport {
+    // true when every character can be drawn by its font chain
+    pub supported: bool,
+    pub author_missing: Vec<char>,
+    pub title_missing: Vec<char>,
+}
+
+// reports the characters no font of a block can draw, without rendering anything,
+// so the background may be left out
+#[axum_macros::debug_handler]
+pub async fn validate_cover(
+    State(state): State<Arc<AppState>>,
+    CoverRequest(payload): CoverRequest,
+) -> Result<Json<GlyphReport>, AppError> {
+    let errors = layout_errors(&payload);
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+    let (author_font, title_font) = font_chains(&payload, &state)?;
+    let author_missing = author_font.missing(&cased(
+        &payload.author,
+        payload.author_uppercase,
+        &payload.author_position,
+    ));
+    let title_missing = title_font.missing(&cased(
+        &payload.title,
+        payload.title_uppercase,
+        &payload.title_position,
+    ));
+    Ok(Json(GlyphReport {
+        supported: author_missing.is_empty() && title_missing.is_empty(),
+        author_missing,
+        title_missing,
+    }))
+}
 
-    let title_splits = textwrap::wrap(payload.title.as_str(), payload.line_length as usize);
+#[axum_macros::debug_handler]
+pub async fn book_cover_batch(
+    State(state): State<Arc<AppState>>,
+    Json(payloads): Json<Vec<BookCoverParams>>,
+) -> Result<Json<Vec<BatchItem>>, AppError> {
+    if payloads.len() > state.settings.max_batch_size {
+        return Err(AppError::BatchTooLarge(state.settings.max_batch_size));
+    }
+
+    Ok(Json(render_batch(payloads, state, |_| {}).await))
+}
 
-    let rev_title_splits = title_splits
-        .into_iter()
-        .map(|s| s.to_string())
-        .rev()
-        .collect::<Vec<String>>();
+// renders covers concurrently on the shared render pool, reporting each finished index
+pub async fn render_batch(
+    payloads: Vec<BookCoverParams>,
+    state: Arc<AppState>,
+    on_done: impl Fn(usize) + Send + Sync + 'static,
+) -> Vec<BatchItem> {
+    let on_done = Arc::new(on_done);
+    let count = payloads.len();
+    // dropping the set, as a timed out job does, abandons the covers still downloading,
+    // one already drawing runs to its end and only then gives its render worker back
+    let mut tasks = JoinSet::new();
+    for (index, payload) in payloads.into_iter().enumerate() {
+        let state = state.clone();
+        let on_done = on_done.clone();
+        tasks.spawn(async move {
+            let permit = state.render_workers.clone().acquire_owned().await;
+            let result = match permit {
+                Ok(permit) => render_cover(payload, state.clone(), None, Some(permit)).await,
+                Err(e) => Err(anyhow!(e).into()),
+            };
+            on_done(index);
+            (index, result)
+        });
+    }
 
-    let author_font = load_font(payload.author_font.as_str())?;
-    let title_font = load_font(payload.title_font.as_str())?;
+    // a task that panicked leaves its item failed
+    let mut items = (0..count)
+        .map(|index| batch_item(index, Err(anyhow!("batch item failed").into())))
+        .collect::<Vec<_>>();
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((index, result)) = joined {
+            items[index] = batch_item(index, result);
+        }
+    }
+    items
+}
+
+fn batch_item(index: usize, result: Result<Packaged, AppError>) -> BatchItem {
+    match result {
+        Ok(packaged) => BatchItem {
+            index,
+            status: StatusCode::OK.as_u16(),
+            content_type: Some(packaged.content_type),
+            data: Some(STANDARD.encode(packaged.bytes)),
+            error: None,
+        },
+        Err(error) => {
+            let (status, message) = error.parts();
+            BatchItem {
+                index,
+                status: status.as_u16(),
+                content_type: None,
+                data: None,
+                error: Some(message),
+            }
+        }
+    }
+}
+
+// fetches the assets of a cover, draws the text and encodes the result
+// a render worker permit is held until drawing ends, even when the caller gave up on it
+pub async fn render_cover(
+    payload: BookCoverParams,
+    state: Arc<AppState>,
+    accept: Option<String>,
+    permit: Option<OwnedSemaphorePermit>,
+) -> Result<Packaged, AppError> {
+    payload.validate()?;
+    // fonts are checked before any download so a typo fails fast
+    let (author_font, title_font) = font_chains(&payload, &state)?;
+
+    let max_bytes = state.settings.upload_max_bytes;
+    let mut image = match (payload.image_upload, payload.image_base64) {
+        (Some(bytes), _) => Image::from_bytes(&bytes, max_bytes)?,
+        (None, Some(encoded)) => Image::from_bytes(&decode_base64(&encoded)?, max_bytes)?,
+        (None, None) => Image::from_url(payload.image_url.as_str(), state.clone()).await?,
+    };
+
+    // lines are cased and measured as they will be drawn, placements that rewrap only get
+    // the breaks
+    let author_lines = hard_lines(
+        &cased(
+            &payload.author,
+            payload.author_uppercase,
+            &payload.author_position,
+        ),
+        &author_font,
+    );
+    let title = cased(
+        &payload.title,
+        payload.title_uppercase,
+        &payload.title_position,
+    );
+    let title_splits = match payload.title_position.rewraps() {
+        true => hard_lines(&title, &title_font),
+        false => wrap_balanced(
+            &title,
+            &title_font,
+            payload.line_length as usize,
+            payload.hyphenate,
+        ),
+    };
+
+    let mut author_style = payload.author_style;
+    let mut title_style = payload.title_style;
+    load_texture(&mut author_style, &state).await?;
+    load_texture(&mut title_style, &state).await?;
 
     let author = OverlayText {
-        text_list: vec![payload.author],
-        color: (255, 255, 255),
+        text_list: author_lines,
+        style: author_style,
         offset: (0, 0),
         alpha: payload.alfa,
         font: author_font,
         position: payload.author_position,
-        blend: payload.blend_mode,
+        blend: payload.author_blend_mode.unwrap_or(payload.blend_mode),
+        max_height: payload.author_max_height,
+        writing_mode: payload.author_writing_mode,
+        rotation: payload.author_rotation,
+        arc_radius: payload.author_arc_radius,
     };
 
     let title = OverlayText {
-        text_list: rev_title_splits.clone(),
-        color: (255, 255, 255),
+        text_list: title_splits,
+        style: title_style,
         offset: (0, 0),
         alpha: payload.alfa,
-        font: title_font.clone(),
+        font: title_font,
         position: payload.title_position,
-        blend: payload.blend_mode,
+        blend: payload.title_blend_mode.unwrap_or(payload.blend_mode),
+        max_height: payload.title_max_height,
+        writing_mode: payload.title_writing_mode,
+        rotation: payload.title_rotation,
+        arc_radius: payload.title_arc_radius,
+    };
+
+    let output = payload.output;
+    let renditions = payload.renditions;
+    // drawing and encoding are CPU bound, keep them off the async workers
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        image.put_text(author).put_text(title);
+
+        let accept = accept.as_deref();
+        match &renditions {
+            Some(renditions) => package_renditions(&image.dyn_img, renditions, &output, accept),
+            None => encode_with_options(&image.dyn_img, &output, accept).map(Packaged::from),
+        }
+    })
+    .await
+    .map_err(|e| anyhow!("render task failed: {}", e))?
+}
+
+// checks everything a render would for a cover whose background comes later, as the
+// prediction output of /generate, so the caller must not give one
+pub fn check_cover(payload: &BookCoverParams, state: &AppState) -> Result<(), AppError> {
+    let mut errors = layout_errors(payload);
+    if image_sources(payload) > 0 {
+        errors.add("__all__", ValidationError::new("no_image_source"));
+    }
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+    font_chains(payload, state).map(|_| ())
+}
+
+// validation errors of everything but the background source
+fn layout_errors(payload: &BookCoverParams) -> ValidationErrors {
+    let mut errors = match payload.validate() {
+        Ok(()) => ValidationErrors::new(),
+        Err(errors) => errors,
     };
+    // schema level errors are only about the image source
+    errors.errors_mut().remove("__all__");
+    errors
+}
+
+// the author and title fonts followed by their fallbacks, with the chains of their marked spans
+fn font_chains(
+    payload: &BookCoverParams,
+    state: &AppState,
+) -> Result<(FontChain, FontChain), AppError> {
+    let chain = |name: &str,
+                 fallbacks: &Option<Vec<String>>,
+                 style: &TextStyle|
+     -> Result<FontChain, AppError> {
+        let fallbacks = fallbacks
+            .as_ref()
+            .unwrap_or(&payload.fallback_fonts)
+            .iter()
+            .map(|name| state.fonts.find(name).map(|font| font.font.clone()))
+            .collect::<Result<Vec<_>, AppError>>()?;
+        let chain = FontChain::new(state.fonts.find(name)?.font.clone(), fallbacks.clone());
+        Ok(match &style.emphasis {
+            Some(emphasis) => {
+                let marked = match &emphasis.font {
+                    Some(name) => FontChain::new(state.fonts.find(name)?.font.clone(), fallbacks),
+                    None => chain.clone(),
+                };
+                chain.with_emphasis(marked, emphasis.size)
+            }
+            None => chain,
+        })
+    };
+    let author = chain(
+        &payload.author_font,
+        &payload.author_fallback_fonts,
+        &payload.author_style,
+    )?;
+    let title = chain(
+        &payload.title_font,
+        &payload.title_fallback_fonts,
+        &payload.title_style,
+    )?;
+    Ok((
+        author.with_direction(payload.author_direction.resolve(&payload.author)),
+        title.with_direction(payload.title_direction.resolve(&payload.title)),
+    ))
+}
 
-    image.put_text(author).put_text(title);
+// the text in the case it is drawn in, capitals follow the placement unless asked otherwise
+fn cased(text: &str, uppercase: Option<bool>, position: &PositionType) -> String {
+    match uppercase.unwrap_or_else(|| position.uppercases()) {
+        true => text.to_uppercase(),
+        false => text.to_string(),
+    }
+}
+
+fn decode_base64(encoded: &str) -> Result<Vec<u8>, AppError> {
+    let data = match encoded.split_once("base64,") {
+        Some((prefix, data)) if prefix.starts_with("data:") => data,
+        _ => encoded,
+    };
+    STANDARD
+        .decode(data.trim())
+        .map_err(|e| AppError::InvalidUpload(format!("image_base64: {}", e)))
+}
 
-    let mut buf: Vec<u8> = Vec::new();
-    image
-        .dyn_img
-        .write_to(&mut Cursor::new(&mut buf), image::ImageOutputFormat::Png)?;
-    Ok(buf)
+// downloads the texture image of a texture fill
+async fn load_texture(style: &mut TextStyle, state: &Arc<AppState>) -> Result<(), AppError> {
+    if let Some(Fill::Texture { url, image }) = &mut style.fill {
+        let texture = Image::from_url(url, state.clone()).await?;
+        *image = Some(Arc::new(texture.dyn_img));
+    }
+    Ok(())
 }
@@ -1,66 +1,77 @@
-use std::{sync::Arc, time::Duration};
-
-use crate::{error::AppError, router::AppState};
-use rusttype::{Font, Scale};
+use rusttype::Scale;
 use unicode_segmentation::UnicodeSegmentation;
 
-const FONTS_DIR: &'static str = "fonts";
+use super::chain::FontChain;
+use super::wrap::MeasuredText;
 
 // calculates font size for a given width
-pub fn calc_font_size(width: u32, text: &str, font: &Font) -> Scale {
+pub fn calc_font_size(width: u32, text: &str, font: &FontChain) -> Scale {
     let mut scale = Scale::uniform(1.0);
-    let mut glyph_width = 0.0;
-    for c in text.chars() {
-        glyph_width += font.glyph(c).scaled(scale).h_metrics().advance_width;
-    }
+    let glyph_width = font.advance_width(text, scale);
     scale.x *= width as f32 / glyph_width;
     scale.y *= width as f32 / glyph_width;
     scale
 }
 
-pub fn calc_text_width(text: &str, font: &Font, scale: Scale) -> u32 {
-    let mut glyph_width = 0.0;
-    for c in text.chars() {
-        glyph_width += font.glyph(c).scaled(scale).h_metrics().advance_width;
-    }
-    glyph_width as u32
+pub fn calc_text_width(text: &str, font: &FontChain, scale: Scale) -> u32 {
+    font.advance_width(text, scale) as u32
 }
 
-pub fn load_font(font_file_name: &str) -> Result<Font<'static>, AppError> {
-    let font_path = format!("{}/{}", FONTS_DIR, font_file_name);
-    let font_file_data = std::fs::read(&font_path)?;
-    match Font::try_from_vec(font_file_data) {
-        Some(font) => Ok(font),
-        None => Err(AppError::FontNotFound),
-    }
+// vertical distance between two consecutive baselines
+pub fn line_height(font: &FontChain, scale: Scale) -> f32 {
+    let v_metrics = font.v_metrics(scale);
+    v_metrics.ascent - v_metrics.descent + v_metrics.line_gap
 }
 
-pub fn less_than(num: usize, text_list: Vec<String>) -> bool {
-    for text in text_list {
-        if text.graphemes(true).count() < num {
-            return true;
-        }
-    }
-    false
-}
+// wraps text into lines and picks the largest scale that fits both max width and max height
+pub fn fit_lines(
+    text: &str,
+    font: &FontChain,
+    max_width: u32,
+    max_height: f32,
+) -> (Vec<String>, Scale) {
+    let unit_height = line_height(font, Scale::uniform(1.0));
+    let graphemes = text.graphemes(true).count().max(1);
+    let measured = MeasuredText::new(text, font);
+    // sizes allowed by the width and by the height of the lines wrapped at a measure
+    let sizes = |width: usize| {
+        let lines = measured.wrap(width);
+        let by_width = calc_font_size(max_width, &widest_line(&lines, font), font).y;
+        let by_height = max_height / (lines.len() as f32 * unit_height);
+        (lines, by_width, by_height)
+    };
 
-pub fn longest_str(str_list: &Vec<String>) -> String {
-    let mut largest = String::new();
-    for s in str_list {
-        if s.graphemes(true).count() > largest.graphemes(true).count() {
-            largest = s.to_string();
+    // narrower measures give more lines, growing the size the width allows and shrinking
+    // the one the height allows, the best fit sits where the two cross
+    let mut best = (vec![text.to_string()], Scale::uniform(0.0));
+    let (mut low, mut high) = (1, graphemes);
+    while low <= high {
+        let width = (low + high) / 2;
+        let (lines, by_width, by_height) = sizes(width);
+        let size = by_width.min(by_height);
+        if size > best.1.y {
+            best = (lines, Scale::uniform(size));
+        }
+        match by_width < by_height {
+            true => high = width - 1,
+            false => low = width + 1,
         }
     }
-    largest
+    best
 }
 
-pub async fn kill_after(secs: Duration, state: Arc<AppState>, url: String) {
-    tokio::spawn(async move {
-        tokio::time::sleep(secs).await;
-        let mut img_map = state.images.lock().unwrap();
-        match img_map.remove_entry(&url) {
-            Some((url, _bytes)) => println!("Removing: {}", url),
-            None => println!("Nothing to remove"),
-        }
-    });
+// the line that sets widest once shaped, which is not always the one with the most graphemes
+pub fn widest_line(lines: &[String], font: &FontChain) -> String {
+    let scale = Scale::uniform(1.0);
+    lines
+        .iter()
+        .map(|line| (font.advance_width(line, scale), line))
+        .fold((0.0, String::new()), |widest, (width, line)| {
+            if width > widest.0 {
+                (width, line.to_string())
+            } else {
+                widest
+            }
+        })
+        .1
 }
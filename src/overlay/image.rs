@@ -1,24 +1,36 @@
 use std::sync::Arc;
-use std::time::Duration;
 
 use crate::error::AppError;
-use crate::overlay::helpers::{calc_font_size, calc_text_width, kill_after};
+use crate::fetch::fetch_image;
+use crate::overlay::helpers::{calc_font_size, calc_text_width, fit_lines, line_height};
 use crate::router::AppState;
 use image::DynamicImage;
 use image::{GenericImage, GenericImageView};
-use rusttype::{point, Font, PositionedGlyph, Scale};
+use rusttype::{point, Point, PositionedGlyph, Scale};
 use serde::{Deserialize, Serialize};
+use validator::ValidationError;
 
-use super::helpers::longest_str;
+use super::chain::FontChain;
+use super::helpers::widest_line;
+use super::mask::Mask;
+use super::style::{Color, TextStyle};
 
 pub struct OverlayText {
     pub text_list: Vec<String>,
-    pub color: (u8, u8, u8),
+    pub style: TextStyle,
     pub offset: (i32, i32),
     pub alpha: f32,
-    pub font: Font<'static>,
+    pub font: FontChain,
     pub position: PositionType,
     pub blend: BlendMode,
+    // fraction of the image height the text block may take (TopCenter)
+    pub max_height: f32,
+    pub writing_mode: WritingMode,
+    // degrees clockwise around the center of the block
+    pub rotation: f32,
+    // bends lines along a circle, radius as a fraction of the image width,
+    // positive arches up like a badge and negative sags like the bottom of a seal
+    pub arc_radius: Option<f32>,
 }
 
 #[derive(Clone, Deserialize, Serialize)]
@@ -28,12 +40,183 @@ pub enum PositionType {
     BottomSides,
     BottomLeft,
     BottomCenter,
+    // box in normalized (0..1) image coordinates
+    Custom {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        #[serde(default)]
+        h_align: HAlign,
+        #[serde(default)]
+        v_align: VAlign,
+        #[serde(default)]
+        fit: FitMode,
+    },
 }
 
-#[derive(Clone, Copy, Deserialize, Serialize)]
+// vertical modes lay the block out on the image turned a quarter clockwise, so lines become
+// columns read top to bottom and the first one sits on the right
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum WritingMode {
+    #[default]
+    Horizontal,
+    // upright glyphs stacked down each column
+    Stacked,
+    // horizontal lines rotated 90 degrees clockwise, as on a book spine
+    Rotated,
+}
+
+impl WritingMode {
+    pub fn is_vertical(self) -> bool {
+        self != WritingMode::Horizontal
+    }
+}
+
+// base direction of a text block, Auto follows the first strong character
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Direction {
+    #[default]
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+impl Direction {
+    // settles Auto against the text, text without strong characters reads left to right
+    pub fn resolve(self, text: &str) -> Direction {
+        match self {
+            Direction::Auto => {
+                let bidi = unicode_bidi::BidiInfo::new(text, None);
+                match bidi.paragraphs.first() {
+                    Some(paragraph) if paragraph.level.is_rtl() => Direction::Rtl,
+                    _ => Direction::Ltr,
+                }
+            }
+            direction => direction,
+        }
+    }
+
+    pub fn is_rtl(self) -> bool {
+        self == Direction::Rtl
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+pub enum HAlign {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+pub enum VAlign {
+    Top,
+    #[default]
+    Middle,
+    Bottom,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+pub enum FitMode {
+    // rewraps the text to get the largest size that fits the box
+    Wrap,
+    // keeps the lines, longest one fills the box width
+    #[default]
+    Width,
+    // every line fills the box width on its own
+    Stretch,
+}
+
+impl PositionType {
+    // the bottom placements draw their text in capitals
+    pub fn uppercases(&self) -> bool {
+        matches!(
+            self,
+            PositionType::BottomStretch
+                | PositionType::BottomSides
+                | PositionType::BottomLeft
+                | PositionType::BottomCenter
+        )
+    }
+
+    // placements that wrap the text again to fill their area, only keeping its line breaks
+    pub fn rewraps(&self) -> bool {
+        matches!(
+            self,
+            PositionType::TopCenter
+                | PositionType::Custom {
+                    fit: FitMode::Wrap,
+                    ..
+                }
+        )
+    }
+
+    // the same placement on the image turned a quarter clockwise
+    pub fn quarter_turned(&self) -> PositionType {
+        match *self {
+            PositionType::Custom {
+                x,
+                y,
+                width,
+                height,
+                h_align,
+                v_align,
+                fit,
+            } => PositionType::Custom {
+                x: y,
+                y: 1.0 - x - width,
+                width: height,
+                height: width,
+                h_align,
+                v_align,
+                fit,
+            },
+            ref position => position.clone(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if let PositionType::Custom {
+            x,
+            y,
+            width,
+            height,
+            ..
+        } = *self
+        {
+            let in_range = |v: f32| (0.0..=1.0).contains(&v);
+            if !(in_range(x) && in_range(y) && in_range(width) && in_range(height))
+                || x + width > 1.0
+                || y + height > 1.0
+                || width == 0.0
+                || height == 0.0
+            {
+                let mut error = ValidationError::new("custom_box");
+                error.message = Some("custom box must lie within 0..1 of the image".into());
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub enum BlendMode {
     None,
     Overlay,
+    Multiply,
+    Screen,
+    SoftLight,
+    HardLight,
+    ColorDodge,
+    ColorBurn,
+    Difference,
+    Exclusion,
+    Lighten,
+    Darken,
+    Luminosity,
 }
 
 #[derive(Debug)]
@@ -43,48 +226,53 @@ pub struct Image {
 }
 
 impl Image {
-    pub fn put_text(&mut self, overlay: OverlayText) -> &mut Image {
-        let (img_width, img_height) = self.dyn_img.dimensions();
+    pub fn put_text(&mut self, mut overlay: OverlayText) -> &mut Image {
+        let vertical = overlay.writing_mode.is_vertical();
+        overlay.font = overlay.font.with_writing_mode(overlay.writing_mode);
+        // vertical blocks are placed on the image turned a quarter clockwise
+        let (width, height) = self.dyn_img.dimensions();
+        let (img_width, img_height) = match vertical {
+            true => (height, width),
+            false => (width, height),
+        };
+        let position = match vertical {
+            true => overlay.position.quarter_turned(),
+            false => overlay.position.clone(),
+        };
         let mut stacked_height: f32 = 0.0;
         let mut padding_t: u32 = 50;
         let padding_l: u32 = 50;
-
-        match overlay.position {
+        // covers narrower than the padding still get a sliver to draw in
+        let usable_width = img_width.saturating_sub(padding_l).max(1);
+        // start of every line on the layout canvas
+        let mut placed: Vec<(String, Scale, Point<f32>)> = Vec::new();
+        // right to left blocks start from the right edge where the layout hugs a side
+        let rtl = overlay.font.direction().is_rtl();
+
+        match position {
             PositionType::TopCenter => {
-                stacked_height += 20.0;
-                for text in overlay.text_list {
-                    let scale = Scale::uniform(24.0);
-                    let v_metrics = overlay.font.v_metrics(scale);
+                let text = overlay.text_list.join("\n");
+                let max_height = img_height as f32 * overlay.max_height;
+                let (lines, scale) = fit_lines(&text, &overlay.font, usable_width, max_height);
+                let v_metrics = overlay.font.v_metrics(scale);
 
+                stacked_height += padding_t as f32 / 2.0 + v_metrics.ascent;
+                for text in lines {
                     let left = (img_width as f32 / 2.0)
                         - calc_text_width(text.as_str(), &overlay.font, scale) as f32 / 2.0;
-                    let top = stacked_height + padding_t as f32 / 2.0;
-
-                    let offset = point(left, top);
 
-                    let glyphs: Vec<PositionedGlyph> =
-                        overlay.font.layout(&text, scale, offset).collect();
+                    let offset = point(left, stacked_height);
 
-                    self.dyn_img = draw_glyphs(
-                        glyphs,
-                        overlay.alpha,
-                        overlay.color,
-                        overlay.offset,
-                        overlay.blend,
-                        self.dyn_img.clone(),
-                    );
+                    placed.push((text, scale, offset));
 
                     // update stacked height
-                    stacked_height += v_metrics.ascent;
-                    // update padding y
-                    padding_t += 35;
+                    stacked_height += line_height(&overlay.font, scale);
                 }
-                self
             }
             PositionType::BottomStretch => {
-                for text in overlay.text_list {
-                    let text = text.to_uppercase();
-                    let scale = calc_font_size(img_width - padding_l, &text, &overlay.font);
+                for text in overlay.text_list.iter().rev() {
+                    let text = text.clone();
+                    let scale = calc_font_size(usable_width, &text, &overlay.font);
                     let v_metrics = overlay.font.v_metrics(scale);
 
                     let left = padding_l as f32 / 2.0;
@@ -92,29 +280,18 @@ impl Image {
 
                     let offset = point(left, top);
 
-                    let glyphs: Vec<PositionedGlyph> =
-                        overlay.font.layout(&text, scale, offset).collect();
-
-                    self.dyn_img = draw_glyphs(
-                        glyphs,
-                        overlay.alpha,
-                        overlay.color,
-                        overlay.offset,
-                        overlay.blend,
-                        self.dyn_img.clone(),
-                    );
+                    placed.push((text, scale, offset));
 
                     // update stacked height
                     stacked_height += v_metrics.ascent;
                     // update padding y
                     padding_t += 35;
                 }
-                self
             }
             PositionType::BottomSides => {
-                let mut left_side = true;
-                for text in overlay.text_list {
-                    let text = text.to_uppercase();
+                let mut left_side = !rtl;
+                for text in overlay.text_list.iter().rev() {
+                    let text = text.clone();
                     let scale = Scale::uniform(56.0);
                     let v_metrics = overlay.font.v_metrics(scale);
 
@@ -130,17 +307,7 @@ impl Image {
                         point(left, top)
                     };
 
-                    let glyphs: Vec<PositionedGlyph> =
-                        overlay.font.layout(&text, scale, offset).collect();
-
-                    self.dyn_img = draw_glyphs(
-                        glyphs,
-                        overlay.alpha,
-                        overlay.color,
-                        overlay.offset,
-                        overlay.blend,
-                        self.dyn_img.clone(),
-                    );
+                    placed.push((text, scale, offset));
 
                     // update stacked height
                     stacked_height += v_metrics.ascent;
@@ -149,45 +316,39 @@ impl Image {
                     // update left side
                     left_side = !left_side;
                 }
-                self
             }
             PositionType::BottomLeft => {
-                let longest_line = longest_str(&overlay.text_list);
-                let scale = calc_font_size(img_width - padding_l, &longest_line, &overlay.font);
-                for text in overlay.text_list {
-                    let text = text.to_uppercase();
+                let longest_line = widest_line(&overlay.text_list, &overlay.font);
+                let scale = calc_font_size(usable_width, &longest_line, &overlay.font);
+                for text in overlay.text_list.iter().rev() {
+                    let text = text.clone();
                     let v_metrics = overlay.font.v_metrics(scale);
 
                     let offset = {
-                        let left = padding_l as f32 / 2.0;
+                        let left = if rtl {
+                            img_width as f32
+                                - padding_l as f32 / 2.0
+                                - calc_text_width(text.as_str(), &overlay.font, scale) as f32
+                        } else {
+                            padding_l as f32 / 2.0
+                        };
                         let top = img_height as f32 - stacked_height - padding_t as f32 / 2.0;
                         point(left, top)
                     };
 
-                    let glyphs: Vec<PositionedGlyph> =
-                        overlay.font.layout(&text, scale, offset).collect();
-
-                    self.dyn_img = draw_glyphs(
-                        glyphs,
-                        overlay.alpha,
-                        overlay.color,
-                        overlay.offset,
-                        overlay.blend,
-                        self.dyn_img.clone(),
-                    );
+                    placed.push((text, scale, offset));
 
                     // update stacked height
                     stacked_height += v_metrics.ascent;
                     // update padding y
                     padding_t += 35;
                 }
-                self
             }
             PositionType::BottomCenter => {
-                let longest_line = longest_str(&overlay.text_list);
-                let scale = calc_font_size(img_width - padding_l, &longest_line, &overlay.font);
-                for text in overlay.text_list {
-                    let text = text.to_uppercase();
+                let longest_line = widest_line(&overlay.text_list, &overlay.font);
+                let scale = calc_font_size(usable_width, &longest_line, &overlay.font);
+                for text in overlay.text_list.iter().rev() {
+                    let text = text.clone();
                     let v_metrics = overlay.font.v_metrics(scale);
 
                     let offset = {
@@ -197,60 +358,181 @@ impl Image {
                         point(left, top)
                     };
 
-                    let glyphs: Vec<PositionedGlyph> =
-                        overlay.font.layout(&text, scale, offset).collect();
-
-                    self.dyn_img = draw_glyphs(
-                        glyphs,
-                        overlay.alpha,
-                        overlay.color,
-                        overlay.offset,
-                        overlay.blend,
-                        self.dyn_img.clone(),
-                    );
+                    placed.push((text, scale, offset));
 
                     // update stacked height
                     stacked_height += v_metrics.ascent;
                     // update padding y
                     padding_t += 35;
                 }
-                self
+            }
+            PositionType::Custom {
+                x,
+                y,
+                width,
+                height,
+                h_align,
+                v_align,
+                fit,
+            } => {
+                let box_left = x * img_width as f32;
+                let box_top = y * img_height as f32;
+                let box_width = width * img_width as f32;
+                let box_height = height * img_height as f32;
+                let unit_height = line_height(&overlay.font, Scale::uniform(1.0));
+
+                let mut lines: Vec<(String, Scale)> = match fit {
+                    FitMode::Wrap => {
+                        let text = overlay.text_list.join("\n");
+                        let (lines, scale) =
+                            fit_lines(&text, &overlay.font, (box_width as u32).max(1), box_height);
+                        lines.into_iter().map(|line| (line, scale)).collect()
+                    }
+                    FitMode::Width => {
+                        let longest_line = widest_line(&overlay.text_list, &overlay.font);
+                        let by_width =
+                            calc_font_size(box_width as u32, &longest_line, &overlay.font).y;
+                        let by_height = box_height / (overlay.text_list.len() as f32 * unit_height);
+                        let scale = Scale::uniform(by_width.min(by_height));
+                        overlay
+                            .text_list
+                            .iter()
+                            .map(|line| (line.clone(), scale))
+                            .collect()
+                    }
+                    FitMode::Stretch => overlay
+                        .text_list
+                        .iter()
+                        .map(|line| {
+                            let scale = calc_font_size(box_width as u32, line, &overlay.font);
+                            (line.clone(), scale)
+                        })
+                        .collect(),
+                };
+
+                // shrink everything proportionally if the lines overflow the box
+                let block_height: f32 = lines
+                    .iter()
+                    .map(|(_, scale)| line_height(&overlay.font, *scale))
+                    .sum();
+                if block_height > box_height {
+                    let shrink = box_height / block_height;
+                    for (_, scale) in lines.iter_mut() {
+                        *scale = Scale::uniform(scale.y * shrink);
+                    }
+                }
+                let block_height = block_height.min(box_height);
+
+                stacked_height += match v_align {
+                    VAlign::Top => box_top,
+                    VAlign::Middle => box_top + (box_height - block_height) / 2.0,
+                    VAlign::Bottom => box_top + box_height - block_height,
+                };
+                for (text, scale) in lines {
+                    let text_width = calc_text_width(text.as_str(), &overlay.font, scale) as f32;
+                    let left = match h_align {
+                        HAlign::Left => box_left,
+                        HAlign::Center => box_left + (box_width - text_width) / 2.0,
+                        HAlign::Right => box_left + box_width - text_width,
+                    };
+                    let top = stacked_height + overlay.font.v_metrics(scale).ascent;
+
+                    let offset = point(left, top);
+
+                    placed.push((text, scale, offset));
+
+                    // update stacked height
+                    stacked_height += line_height(&overlay.font, scale);
+                }
             }
         }
+
+        let mut glyphs: Vec<(PositionedGlyph, bool)> = Vec::new();
+        for (text, scale, offset) in &placed {
+            let start = match overlay.writing_mode {
+                // a line on the turned canvas becomes a column, centered on its line box
+                WritingMode::Stacked => {
+                    let v_metrics = overlay.font.v_metrics(*scale);
+                    point(
+                        img_height as f32 - offset.y + (v_metrics.ascent + v_metrics.descent) / 2.0,
+                        offset.x,
+                    )
+                }
+                _ => *offset,
+            };
+            glyphs.extend(overlay.font.layout_spans(text, *scale, start));
+        }
+
+        draw_glyphs(glyphs, &overlay, &mut self.dyn_img);
+        self
     }
 
     // creates Image from image URL
     pub async fn from_url(url: &str, state: Arc<AppState>) -> Result<Image, AppError> {
-        let mut img_bytes: Vec<u8> = Vec::new();
-        {
-            let img_map = state.images.lock().unwrap();
-            img_bytes = match img_map.get(&url.to_string()) {
-                Some(bytes) => bytes.clone(),
-                None => img_bytes,
-            }
-        }
-        if img_bytes.len() > 0 {
-            let image = image::load_from_memory(&img_bytes)?;
-            Ok(Image {
+        if let Some(cached) = state.images.get(url) {
+            let image = match cached.decoded {
+                Some(decoded) => (*decoded).clone(),
+                None => image::load_from_memory(&cached.bytes)?,
+            };
+            return Ok(Image {
                 dyn_img: image,
                 url: url.to_string(),
+            });
+        }
+
+        // concurrent misses for the same URL share one download
+        let image = state
+            .downloads
+            .run(url, || async {
+                Image::load(url, &state)
+                    .await
+                    .map(Arc::new)
+                    .map_err(Arc::new)
             })
-        } else {
-            let response = reqwest::get(url).await?;
-            let bytes = response.bytes().await?;
-            {
-                let mut img_map = state.images.lock().unwrap();
-                img_map
-                    .entry(url.to_string())
-                    .or_insert(Vec::from(bytes.clone()));
+            .await?;
+        Ok(Image {
+            dyn_img: (*image).clone(),
+            url: url.to_string(),
+        })
+    }
+
+    // decodes an uploaded image, uploads never go through the URL caches
+    pub fn from_bytes(bytes: &[u8], max_bytes: usize) -> Result<Image, AppError> {
+        if bytes.len() > max_bytes {
+            return Err(AppError::ImageTooLarge(max_bytes));
+        }
+        image::guess_format(bytes)
+            .map_err(|_| AppError::UnsupportedImage("unknown file signature".to_string()))?;
+        Ok(Image {
+            dyn_img: image::load_from_memory(bytes)?,
+            url: String::new(),
+        })
+    }
+
+    // reads the image from the disk cache or downloads it, filling both caches
+    async fn load(url: &str, state: &AppState) -> Result<DynamicImage, AppError> {
+        if let Some(disk) = &state.disk_images {
+            if let Some(bytes) = disk.get(url).await {
+                match image::load_from_memory(&bytes) {
+                    Ok(image) => {
+                        state.images.insert(url, bytes, Some(image.clone()));
+                        return Ok(image);
+                    }
+                    // unreadable file, drop it and download again
+                    Err(_) => disk.remove(url).await,
+                }
             }
-            kill_after(Duration::from_secs(120), state, url.to_string()).await;
-            let image = image::load_from_memory(&bytes)?;
-            Ok(Image {
-                dyn_img: image,
-                url: url.to_string(),
-            })
         }
+
+        let bytes = fetch_image(url, &state.settings).await?;
+        let image = image::load_from_memory(&bytes)?;
+        if let Some(disk) = &state.disk_images {
+            if let Err(e) = disk.insert(url, &bytes).await {
+                println!("Disk cache write failed for {}: {}", url, e);
+            }
+        }
+        state.images.insert(url, bytes, Some(image.clone()));
+        Ok(image)
     }
 
     pub fn blend_mode(
@@ -260,65 +542,325 @@ impl Image {
         alpha: f32,
         v: f32,
     ) -> [u8; 4] {
-        match mode {
-            BlendMode::None => [
-                (pixel_rgb.0 as f32 * (1.0 - alpha * v) + color_rgb.0 as f32 * alpha * v) as u8,
-                (pixel_rgb.1 as f32 * (1.0 - alpha * v) + color_rgb.1 as f32 * alpha * v) as u8,
-                (pixel_rgb.2 as f32 * (1.0 - alpha * v) + color_rgb.2 as f32 * alpha * v) as u8,
-                255,
-            ],
-            BlendMode::Overlay => {
-                let r = if (pixel_rgb.0 as f32) < 128.0 {
-                    2.0 * pixel_rgb.0 as f32 * color_rgb.0 as f32 / 255.0
-                } else {
-                    255.0
-                        - 2.0 * (255.0 - pixel_rgb.0 as f32) * (255.0 - color_rgb.0 as f32) / 255.0
-                };
-                let g = if (pixel_rgb.1 as f32) < 128.0 {
-                    2.0 * pixel_rgb.1 as f32 * color_rgb.1 as f32 / 255.0
-                } else {
-                    255.0
-                        - 2.0 * (255.0 - pixel_rgb.1 as f32) * (255.0 - color_rgb.1 as f32) / 255.0
-                };
-                let b = if (pixel_rgb.2 as f32) < 128.0 {
-                    2.0 * pixel_rgb.2 as f32 * color_rgb.2 as f32 / 255.0
+        let base = [pixel_rgb.0, pixel_rgb.1, pixel_rgb.2].map(|c| c as f32 / 255.0);
+        let top = [color_rgb.0, color_rgb.1, color_rgb.2].map(|c| c as f32 / 255.0);
+
+        let blended = match mode {
+            BlendMode::Luminosity => set_lum(base, lum(top)),
+            _ => [0, 1, 2].map(|i| blend_channel(mode, base[i], top[i])),
+        };
+
+        let mix = |b: f32, c: f32| (b * 255.0 * (1.0 - alpha * v) + c * 255.0 * alpha * v) as u8;
+        [
+            mix(base[0], blended[0]),
+            mix(base[1], blended[1]),
+            mix(base[2], blended[2]),
+            255,
+        ]
+    }
+}
+
+// blends one 0..1 channel of the text color (top) over the background (base)
+fn blend_channel(mode: BlendMode, base: f32, top: f32) -> f32 {
+    let screen = |b: f32, t: f32| 1.0 - (1.0 - b) * (1.0 - t);
+    let hard_light = |b: f32, t: f32| {
+        if t < 0.5 {
+            2.0 * b * t
+        } else {
+            screen(b, 2.0 * t - 1.0)
+        }
+    };
+    match mode {
+        BlendMode::None | BlendMode::Luminosity => top,
+        BlendMode::Overlay => hard_light(top, base),
+        BlendMode::Multiply => base * top,
+        BlendMode::Screen => screen(base, top),
+        BlendMode::SoftLight => {
+            if top <= 0.5 {
+                base - (1.0 - 2.0 * top) * base * (1.0 - base)
+            } else {
+                let d = if base <= 0.25 {
+                    ((16.0 * base - 12.0) * base + 4.0) * base
                 } else {
-                    255.0
-                        - 2.0 * (255.0 - pixel_rgb.2 as f32) * (255.0 - color_rgb.2 as f32) / 255.0
+                    base.sqrt()
                 };
-                [
-                    (pixel_rgb.0 as f32 * (1.0 - alpha * v) + r as f32 * alpha * v) as u8,
-                    (pixel_rgb.1 as f32 * (1.0 - alpha * v) + g as f32 * alpha * v) as u8,
-                    (pixel_rgb.2 as f32 * (1.0 - alpha * v) + b as f32 * alpha * v) as u8,
-                    255,
-                ]
+                base + (2.0 * top - 1.0) * (d - base)
+            }
+        }
+        BlendMode::HardLight => hard_light(base, top),
+        BlendMode::ColorDodge => {
+            if base == 0.0 {
+                0.0
+            } else if top >= 1.0 {
+                1.0
+            } else {
+                (base / (1.0 - top)).min(1.0)
+            }
+        }
+        BlendMode::ColorBurn => {
+            if base >= 1.0 {
+                1.0
+            } else if top <= 0.0 {
+                0.0
+            } else {
+                1.0 - ((1.0 - base) / top).min(1.0)
             }
         }
+        BlendMode::Difference => (base - top).abs(),
+        BlendMode::Exclusion => base + top - 2.0 * base * top,
+        BlendMode::Lighten => base.max(top),
+        BlendMode::Darken => base.min(top),
     }
 }
 
+fn lum(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+// keeps hue and saturation of c while moving its luminosity to l
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    let c = c.map(|v| v + d);
+
+    let l = lum(c);
+    let min = c[0].min(c[1]).min(c[2]);
+    let max = c[0].max(c[1]).max(c[2]);
+    c.map(|v| {
+        if min < 0.0 {
+            l + (v - l) * l / (l - min)
+        } else if max > 1.0 {
+            l + (v - l) * (1.0 - l) / (max - l)
+        } else {
+            v
+        }
+    })
+}
+
+// glyphs come flagged when they belong to a *marked* span
 pub fn draw_glyphs(
-    glyphs: Vec<PositionedGlyph>,
+    glyphs: Vec<(PositionedGlyph, bool)>,
+    overlay: &OverlayText,
+    image: &mut DynamicImage,
+) {
+    let style = &overlay.style;
+    let margin = style.margin();
+    let image_width = image.width();
+    let (glyphs, marked): (Vec<_>, Vec<_>) = glyphs.into_iter().unzip();
+    let turned = overlay.rotation != 0.0 || overlay.arc_radius.is_some();
+    let bent = turned.then(|| bend(glyphs.clone(), overlay, image_width));
+    // coverage of the glyphs with the given flag, of all of them without one
+    let coverage = |only: Option<bool>| {
+        let keep = |i: usize| only.is_none_or(|only| marked[i] == only);
+        let mask = match &bent {
+            Some(bent) => Mask::from_turned_glyphs(
+                &bent
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| keep(*i))
+                    .map(|(_, glyph)| glyph.clone())
+                    .collect::<Vec<_>>(),
+                margin,
+            ),
+            None => Mask::from_glyphs(
+                &glyphs
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| keep(*i))
+                    .map(|(_, glyph)| glyph.clone())
+                    .collect::<Vec<_>>(),
+                margin,
+            ),
+        };
+        match mask {
+            // rotated lines were laid out on the turned canvas, turn the coverage back
+            Some(mask) if overlay.writing_mode == WritingMode::Rotated => {
+                Some(mask.turn_clockwise(image_width))
+            }
+            mask => mask,
+        }
+    };
+    let mask = match coverage(None) {
+        Some(mask) => mask,
+        None => return,
+    };
+    let outline = style
+        .stroke
+        .as_ref()
+        .map(|stroke| mask.dilate(stroke.width));
+
+    if let Some(shadow) = &style.shadow {
+        let shadow_mask = outline.as_ref().unwrap_or(&mask).blur(shadow.blur);
+        let offset = (
+            overlay.offset.0 + shadow.offset.0,
+            overlay.offset.1 + shadow.offset.1,
+        );
+        composite(
+            &shadow_mask,
+            &|_, _| shadow.color,
+            1.0,
+            offset,
+            BlendMode::None,
+            image,
+        );
+    }
+    if let (Some(stroke), Some(outline)) = (&style.stroke, &outline) {
+        composite(
+            outline,
+            &|_, _| stroke.color,
+            1.0,
+            overlay.offset,
+            BlendMode::None,
+            image,
+        );
+    }
+
+    // fills are laid out over the glyph box, without the stroke and shadow margin
+    let fill = style.fill.as_ref().map(|fill| {
+        let margin = margin as u32 * 2;
+        fill.painter(mask.width - margin, mask.height - margin)
+    });
+    let paint = |x: i32, y: i32| match &fill {
+        Some(fill) => fill((x - margin) as f32, (y - margin) as f32),
+        None => style.color,
+    };
+    let emphasis_color = style
+        .emphasis
+        .as_ref()
+        .and_then(|emphasis| emphasis.color)
+        .filter(|_| marked.contains(&true));
+    let color = match emphasis_color {
+        Some(color) => color,
+        None => {
+            composite(
+                &mask,
+                &paint,
+                overlay.alpha,
+                overlay.offset,
+                overlay.blend,
+                image,
+            );
+            return;
+        }
+    };
+    // marked spans get their own color, the fill keeps its layout over the whole block
+    if let Some(plain) = coverage(Some(false)) {
+        let (dx, dy) = (plain.left - mask.left, plain.top - mask.top);
+        composite(
+            &plain,
+            &|x, y| paint(x + dx, y + dy),
+            overlay.alpha,
+            overlay.offset,
+            overlay.blend,
+            image,
+        );
+    }
+    if let Some(emphasized) = coverage(Some(true)) {
+        composite(
+            &emphasized,
+            &|_, _| color,
+            overlay.alpha,
+            overlay.offset,
+            overlay.blend,
+            image,
+        );
+    }
+}
+
+// places laid out glyphs along the block arc and turns them with the block rotation,
+// returning each glyph with the angle it is drawn at
+fn bend<'f>(
+    glyphs: Vec<PositionedGlyph<'f>>,
+    overlay: &OverlayText,
+    image_width: u32,
+) -> Vec<(PositionedGlyph<'f>, f32)> {
+    let boxes = glyphs.iter().filter_map(|g| g.pixel_bounding_box());
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+    for bb in boxes {
+        min_x = min_x.min(bb.min.x);
+        min_y = min_y.min(bb.min.y);
+        max_x = max_x.max(bb.max.x);
+        max_y = max_y.max(bb.max.y);
+    }
+    if min_x > max_x {
+        return Vec::new();
+    }
+    let pivot = point((min_x + max_x) as f32 / 2.0, (min_y + max_y) as f32 / 2.0);
+    // the radius is measured at the first baseline, lower lines run on smaller circles
+    let top_baseline = glyphs
+        .iter()
+        .map(|g| g.position().y)
+        .fold(f32::MAX, f32::min);
+    let turn = |p: Point<f32>, angle: f32| {
+        let (sin, cos) = angle.sin_cos();
+        point(p.x * cos - p.y * sin, p.x * sin + p.y * cos)
+    };
+
+    let placed = glyphs.into_iter().map(|g| match overlay.arc_radius {
+        // arc length along each baseline matches the laid out advance
+        Some(radius) => {
+            let radius = radius * image_width as f32;
+            let center = point(pivot.x, top_baseline + radius);
+            let half = g.unpositioned().h_metrics().advance_width / 2.0;
+            let origin = g.position();
+            let rho = radius + top_baseline - origin.y;
+            let rho = match rho.abs() < 1.0 {
+                true => radius.signum(),
+                false => rho,
+            };
+            let angle = (origin.x + half - center.x) / rho;
+            let middle = point(center.x + rho * angle.sin(), center.y - rho * angle.cos());
+            let back = turn(point(half, 0.0), angle);
+            let g = g
+                .into_unpositioned()
+                .positioned(point(middle.x - back.x, middle.y - back.y));
+            (g, angle)
+        }
+        None => (g, 0.0),
+    });
+
+    let rotation = overlay.rotation.to_radians();
+    placed
+        .map(|(g, angle)| {
+            let origin = g.position();
+            let moved = turn(point(origin.x - pivot.x, origin.y - pivot.y), rotation);
+            let g = g
+                .into_unpositioned()
+                .positioned(point(pivot.x + moved.x, pivot.y + moved.y));
+            (g, angle + rotation)
+        })
+        .collect()
+}
+
+// paints the mask coverage onto the image, colored per mask pixel
+fn composite(
+    mask: &Mask,
+    paint: &dyn Fn(i32, i32) -> Color,
     alpha: f32,
-    color: (u8, u8, u8),
     offset: (i32, i32),
     mode: BlendMode,
-    mut image: DynamicImage,
-) -> DynamicImage {
+    image: &mut DynamicImage,
+) {
     let (img_width, img_height) = image.dimensions();
-    for g in glyphs {
-        if let Some(bb) = g.pixel_bounding_box() {
-            g.draw(|x, y, v| {
-                let x = x as i32 + bb.min.x + offset.0;
-                let y = y as i32 + bb.min.y + offset.1;
-                if x >= 0 && x < img_width as i32 && y >= 0 && y < img_height as i32 {
-                    let c = image.get_pixel(x as u32, y as u32);
-                    let rgba = Image::blend_mode(mode, (c[0], c[1], c[2]), color, alpha, v);
-                    let c = image::Rgba(rgba);
-                    image.put_pixel(x as u32, y as u32, c);
-                }
-            });
+    for my in 0..mask.height as i32 {
+        for mx in 0..mask.width as i32 {
+            let v = mask.get(mx, my);
+            if v <= 0.0 {
+                continue;
+            }
+            let x = mask.left + mx + offset.0;
+            let y = mask.top + my + offset.1;
+            if x >= 0 && x < img_width as i32 && y >= 0 && y < img_height as i32 {
+                let color = paint(mx, my);
+                let c = image.get_pixel(x as u32, y as u32);
+                let rgba = Image::blend_mode(
+                    mode,
+                    (c[0], c[1], c[2]),
+                    color.rgb(),
+                    alpha * color.opacity(),
+                    v,
+                );
+                image.put_pixel(x as u32, y as u32, image::Rgba(rgba));
+            }
         }
     }
-    image
 }
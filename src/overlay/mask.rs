@@ -0,0 +1,319 @@
+use ab_glyph_rasterizer::{point as raster_point, Point as RasterPoint, Rasterizer};
+use rusttype::{point, OutlineBuilder, Point, PositionedGlyph, Rect};
+
+// glyph coverage (0..1) rasterized into a canvas-positioned box
+#[derive(Clone)]
+pub struct Mask {
+    pub left: i32,
+    pub top: i32,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<f32>,
+}
+
+impl Mask {
+    pub fn new(left: i32, top: i32, width: u32, height: u32) -> Mask {
+        Mask {
+            left,
+            top,
+            width,
+            height,
+            data: vec![0.0; width as usize * height as usize],
+        }
+    }
+
+    // rasterizes glyphs into a mask covering their bounding boxes plus margin
+    pub fn from_glyphs(glyphs: &[PositionedGlyph], margin: i32) -> Option<Mask> {
+        let boxes = glyphs.iter().filter_map(|g| g.pixel_bounding_box());
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+        for bb in boxes {
+            min_x = min_x.min(bb.min.x);
+            min_y = min_y.min(bb.min.y);
+            max_x = max_x.max(bb.max.x);
+            max_y = max_y.max(bb.max.y);
+        }
+        if min_x > max_x || min_y > max_y {
+            return None;
+        }
+
+        let left = min_x - margin;
+        let top = min_y - margin;
+        let width = (max_x - min_x + 2 * margin) as u32;
+        let height = (max_y - min_y + 2 * margin) as u32;
+        let mut mask = Mask::new(left, top, width, height);
+
+        for g in glyphs {
+            if let Some(bb) = g.pixel_bounding_box() {
+                g.draw(|x, y, v| {
+                    let i = mask.index(x as i32 + bb.min.x - left, y as i32 + bb.min.y - top);
+                    mask.data[i] = (mask.data[i] + v).min(1.0);
+                });
+            }
+        }
+        Some(mask)
+    }
+
+    // rasterizes glyphs turned clockwise by their angle in radians around their origins
+    pub fn from_turned_glyphs(glyphs: &[(PositionedGlyph, f32)], margin: i32) -> Option<Mask> {
+        let boxes = glyphs
+            .iter()
+            .map(|(g, angle)| turned_box(g, *angle))
+            .collect::<Vec<_>>();
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+        for bb in boxes.iter().flatten() {
+            min_x = min_x.min(bb.min.x);
+            min_y = min_y.min(bb.min.y);
+            max_x = max_x.max(bb.max.x);
+            max_y = max_y.max(bb.max.y);
+        }
+        if min_x > max_x || min_y > max_y {
+            return None;
+        }
+
+        let left = min_x - margin;
+        let top = min_y - margin;
+        let width = (max_x - min_x + 2 * margin) as u32;
+        let height = (max_y - min_y + 2 * margin) as u32;
+        let mut mask = Mask::new(left, top, width, height);
+
+        for ((g, angle), bb) in glyphs.iter().zip(&boxes) {
+            let bb = match bb {
+                Some(bb) => bb,
+                None => continue,
+            };
+            let origin = g.position();
+            let mut outline = TurnedOutline {
+                rasterizer: Rasterizer::new(bb.width() as usize, bb.height() as usize),
+                origin: point(origin.x - bb.min.x as f32, origin.y - bb.min.y as f32),
+                sin_cos: angle.sin_cos(),
+                last: raster_point(0.0, 0.0),
+                first: None,
+            };
+            if !g.unpositioned().build_outline(&mut outline) {
+                continue;
+            }
+            outline.rasterizer.for_each_pixel_2d(|x, y, v| {
+                let i = mask.index(x as i32 + bb.min.x - left, y as i32 + bb.min.y - top);
+                mask.data[i] = (mask.data[i] + v).min(1.0);
+            });
+        }
+        Some(mask)
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> f32 {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return 0.0;
+        }
+        self.data[self.index(x, y)]
+    }
+
+    // position in data of a pixel inside the mask, computed in usize like the allocation
+    fn index(&self, x: i32, y: i32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    // turns a mask from a canvas turned a quarter clockwise back onto an image of the given width
+    pub fn turn_clockwise(&self, image_width: u32) -> Mask {
+        let mut out = Mask::new(
+            image_width as i32 - self.top - self.height as i32,
+            self.left,
+            self.height,
+            self.width,
+        );
+        for y in 0..out.height {
+            for x in 0..out.width {
+                let i = out.index(x as i32, y as i32);
+                out.data[i] = self.get(y as i32, (self.height - 1 - x) as i32);
+            }
+        }
+        out
+    }
+
+    // grows the covered area by radius pixels, used for outlines, the edge is antialiased
+    // by the distance to the ink where partly covered pixels count as partway there
+    pub fn dilate(&self, radius: f32) -> Mask {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut distances = self
+            .data
+            .iter()
+            .map(|&v| match v > 0.0 {
+                true => ((1.0 - v) as f64).powi(2),
+                false => FAR,
+            })
+            .collect::<Vec<_>>();
+        for row in distances.chunks_mut(width.max(1)) {
+            let line = squared_distances(row);
+            row.copy_from_slice(&line);
+        }
+        for x in 0..width {
+            let column = (0..height)
+                .map(|y| distances[y * width + x])
+                .collect::<Vec<_>>();
+            for (y, distance) in squared_distances(&column).into_iter().enumerate() {
+                distances[y * width + x] = distance;
+            }
+        }
+
+        let mut out = self.clone();
+        for (v, distance) in out.data.iter_mut().zip(distances) {
+            let weight = (radius + 0.5 - distance.sqrt() as f32).clamp(0.0, 1.0);
+            *v = v.max(weight);
+        }
+        out
+    }
+
+    // approximates a gaussian blur with three box blur passes
+    pub fn blur(&self, radius: f32) -> Mask {
+        let box_radius = (radius / 3.0).round() as i32;
+        if box_radius < 1 {
+            return self.clone();
+        }
+        let mut out = self.clone();
+        for _ in 0..3 {
+            out = out.box_blur(box_radius, true).box_blur(box_radius, false);
+        }
+        out
+    }
+
+    fn box_blur(&self, radius: i32, horizontal: bool) -> Mask {
+        let mut out = Mask::new(self.left, self.top, self.width, self.height);
+        let size = (2 * radius + 1) as f32;
+        let (outer, inner) = if horizontal {
+            (self.height as i32, self.width as i32)
+        } else {
+            (self.width as i32, self.height as i32)
+        };
+        let at = |o: i32, i: i32| if horizontal { (i, o) } else { (o, i) };
+
+        for o in 0..outer {
+            let mut sum: f32 = (-radius..=radius)
+                .map(|i| {
+                    let (x, y) = at(o, i);
+                    self.get(x, y)
+                })
+                .sum();
+            for i in 0..inner {
+                let (x, y) = at(o, i);
+                let pixel = out.index(x, y);
+                out.data[pixel] = sum / size;
+                let (ax, ay) = at(o, i + radius + 1);
+                let (rx, ry) = at(o, i - radius);
+                sum += self.get(ax, ay) - self.get(rx, ry);
+            }
+        }
+        out
+    }
+}
+
+// squared distance standing in for no ink at all, kept finite for the envelope arithmetic
+const FAR: f64 = 1e20;
+
+// squared distance along a line to the nearest source, each source starting at its own value,
+// as the lower envelope of parabolas (Felzenszwalb and Huttenlocher) in linear time
+fn squared_distances(f: &[f64]) -> Vec<f64> {
+    let n = f.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let parabola = |q: usize| f[q] + (q * q) as f64;
+    let crossing = |q: usize, p: usize| (parabola(q) - parabola(p)) / (2.0 * (q - p) as f64);
+    // apexes of the parabolas on the envelope and where each one takes over
+    let mut apexes = vec![0; n];
+    let mut starts = vec![f64::NEG_INFINITY; n + 1];
+    starts[1] = f64::INFINITY;
+    let mut k = 0;
+    for q in 1..n {
+        let mut s = crossing(q, apexes[k]);
+        while k > 0 && s <= starts[k] {
+            k -= 1;
+            s = crossing(q, apexes[k]);
+        }
+        k += 1;
+        apexes[k] = q;
+        starts[k] = s;
+        starts[k + 1] = f64::INFINITY;
+    }
+
+    let mut k = 0;
+    (0..n)
+        .map(|q| {
+            while starts[k + 1] < q as f64 {
+                k += 1;
+            }
+            let d = q as f64 - apexes[k] as f64;
+            d * d + f[apexes[k]]
+        })
+        .collect()
+}
+
+// pixel box of a glyph turned around its origin
+fn turned_box(glyph: &PositionedGlyph, angle: f32) -> Option<Rect<i32>> {
+    let bb = glyph.unpositioned().exact_bounding_box()?;
+    let origin = glyph.position();
+    let (sin, cos) = angle.sin_cos();
+    let corners = [
+        (bb.min.x, bb.min.y),
+        (bb.max.x, bb.min.y),
+        (bb.min.x, bb.max.y),
+        (bb.max.x, bb.max.y),
+    ]
+    .map(|(x, y)| (origin.x + x * cos - y * sin, origin.y + x * sin + y * cos));
+    let min = |pick: fn(&(f32, f32)) -> f32| corners.iter().map(pick).fold(f32::MAX, f32::min);
+    let max = |pick: fn(&(f32, f32)) -> f32| corners.iter().map(pick).fold(f32::MIN, f32::max);
+    Some(Rect {
+        min: point(min(|c| c.0).floor() as i32, min(|c| c.1).floor() as i32),
+        max: point(max(|c| c.0).ceil() as i32, max(|c| c.1).ceil() as i32),
+    })
+}
+
+// feeds a glyph outline into the rasterizer, turned around the glyph origin
+struct TurnedOutline {
+    rasterizer: Rasterizer,
+    origin: Point<f32>,
+    sin_cos: (f32, f32),
+    last: RasterPoint,
+    first: Option<RasterPoint>,
+}
+
+impl TurnedOutline {
+    fn place(&self, x: f32, y: f32) -> RasterPoint {
+        let (sin, cos) = self.sin_cos;
+        raster_point(
+            self.origin.x + x * cos - y * sin,
+            self.origin.y + x * sin + y * cos,
+        )
+    }
+}
+
+impl OutlineBuilder for TurnedOutline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.last = self.place(x, y);
+        self.first = Some(self.last);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.place(x, y);
+        self.rasterizer.draw_line(self.last, p);
+        self.last = p;
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p1, p) = (self.place(x1, y1), self.place(x, y));
+        self.rasterizer.draw_quad(self.last, p1, p);
+        self.last = p;
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (p1, p2, p) = (self.place(x1, y1), self.place(x2, y2), self.place(x, y));
+        self.rasterizer.draw_cubic(self.last, p1, p2, p);
+        self.last = p;
+    }
+
+    fn close(&mut self) {
+        if let Some(first) = self.first.take() {
+            if first != self.last {
+                self.rasterizer.draw_line(self.last, first);
+            }
+        }
+    }
+}
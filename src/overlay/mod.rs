@@ -1,3 +1,8 @@
+pub mod chain;
 pub mod handlers;
 pub mod helpers;
 pub mod image;
+pub mod mask;
+pub mod output;
+pub mod style;
+pub mod wrap;
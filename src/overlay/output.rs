@@ -0,0 +1,326 @@
+use std::io::{Cursor, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use axum::{
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use image::{imageops::FilterType, DynamicImage, ImageOutputFormat};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::error::AppError;
+
+const DEFAULT_QUALITY: u8 = 85;
+const MIN_QUALITY: u8 = 10;
+const AVIF_SPEED: u8 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl OutputFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Webp => "image/webp",
+            OutputFormat::Avif => "image/avif",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Avif => "avif",
+        }
+    }
+
+    pub fn is_lossy(&self) -> bool {
+        !matches!(self, OutputFormat::Png)
+    }
+
+    fn from_mime(mime: &str) -> Option<OutputFormat> {
+        match mime {
+            "image/png" | "image/*" | "*/*" => Some(OutputFormat::Png),
+            "image/jpeg" | "image/jpg" => Some(OutputFormat::Jpeg),
+            "image/webp" => Some(OutputFormat::Webp),
+            "image/avif" => Some(OutputFormat::Avif),
+            _ => None,
+        }
+    }
+
+    // picks the supported format with the highest q value from an Accept header
+    pub fn from_accept(accept: &str) -> Option<OutputFormat> {
+        let mut best: Option<(OutputFormat, f32)> = None;
+        for entry in accept.split(',') {
+            let mut parts = entry.split(';').map(str::trim);
+            let format = match parts.next().and_then(OutputFormat::from_mime) {
+                Some(format) => format,
+                None => continue,
+            };
+            let q = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((format, q));
+            }
+        }
+        best.map(|(format, _)| format)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Validate)]
+pub struct OutputOptions {
+    // when unset the format is negotiated from the Accept header, falling back to PNG
+    #[serde(default)]
+    pub format: Option<OutputFormat>,
+    #[serde(default)]
+    #[validate(range(min = 1, max = 100))]
+    pub quality: Option<u8>,
+    // upper size bound for lossy formats, quality is lowered until it fits
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+impl OutputOptions {
+    pub fn resolve_format(&self, accept: Option<&str>) -> OutputFormat {
+        self.format
+            .or_else(|| accept.and_then(OutputFormat::from_accept))
+            .unwrap_or(OutputFormat::Png)
+    }
+}
+
+pub struct Encoded {
+    pub bytes: Vec<u8>,
+    pub format: OutputFormat,
+    // the format was picked from the Accept header rather than asked for
+    pub negotiated: bool,
+}
+
+impl IntoResponse for Encoded {
+    fn into_response(self) -> Response {
+        Packaged::from(self).into_response()
+    }
+}
+
+pub fn encode(
+    image: &DynamicImage,
+    format: OutputFormat,
+    quality: u8,
+) -> Result<Vec<u8>, AppError> {
+    let mut buf: Vec<u8> = Vec::new();
+    match format {
+        OutputFormat::Png => {
+            image.write_to(&mut Cursor::new(&mut buf), ImageOutputFormat::Png)?;
+        }
+        OutputFormat::Jpeg => {
+            image.write_to(&mut Cursor::new(&mut buf), ImageOutputFormat::Jpeg(quality))?;
+        }
+        OutputFormat::Webp => {
+            let rgba = image.to_rgba8();
+            let encoder = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height());
+            buf.extend_from_slice(&encoder.encode(quality as f32));
+        }
+        OutputFormat::Avif => {
+            let rgba = image.to_rgba8();
+            let pixels: Vec<ravif::RGBA8> = rgba
+                .pixels()
+                .map(|p| ravif::RGBA8::new(p[0], p[1], p[2], p[3]))
+                .collect();
+            let encoded = ravif::Encoder::new()
+                .with_quality(quality as f32)
+                .with_speed(AVIF_SPEED)
+                .encode_rgba(ravif::Img::new(
+                    &pixels[..],
+                    rgba.width() as usize,
+                    rgba.height() as usize,
+                ))
+                .map_err(|e| anyhow!("avif encoding failed: {}", e))?;
+            buf = encoded.avif_file;
+        }
+    }
+    Ok(buf)
+}
+
+// encodes the image honoring the requested format, quality and size budget
+pub fn encode_with_options(
+    image: &DynamicImage,
+    options: &OutputOptions,
+    accept: Option<&str>,
+) -> Result<Encoded, AppError> {
+    let format = options.resolve_format(accept);
+    let mut quality = options.quality.unwrap_or(DEFAULT_QUALITY);
+    let mut bytes = encode(image, format, quality)?;
+
+    if let Some(max_bytes) = options.max_bytes {
+        while format.is_lossy() && bytes.len() > max_bytes && quality > MIN_QUALITY {
+            quality = quality.saturating_sub(10).max(MIN_QUALITY);
+            bytes = encode(image, format, quality)?;
+        }
+    }
+    Ok(Encoded {
+        bytes,
+        format,
+        negotiated: options.format.is_none(),
+    })
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub enum ResizeMode {
+    // scales and crops to fill the exact size
+    #[default]
+    Fill,
+    // scales to fit inside the size, keeping the aspect ratio
+    Fit,
+    // scales to the exact size, ignoring the aspect ratio
+    Exact,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct Rendition {
+    // file name without extension, defaults to WIDTHxHEIGHT
+    #[serde(default)]
+    pub name: Option<String>,
+    #[validate(range(min = 1, max = 8192))]
+    pub width: u32,
+    #[validate(range(min = 1, max = 8192))]
+    pub height: u32,
+    #[serde(default)]
+    pub resize: ResizeMode,
+}
+
+impl Rendition {
+    pub fn file_name(&self, format: OutputFormat) -> String {
+        let name = match &self.name {
+            Some(name) => name.replace(['/', '\\', '"'], "_"),
+            None => format!("{}x{}", self.width, self.height),
+        };
+        format!("{}.{}", name, format.extension())
+    }
+
+    pub fn resize(&self, image: &DynamicImage) -> DynamicImage {
+        let filter = FilterType::Lanczos3;
+        match self.resize {
+            ResizeMode::Fill => image.resize_to_fill(self.width, self.height, filter),
+            ResizeMode::Fit => image.resize(self.width, self.height, filter),
+            ResizeMode::Exact => image.resize_exact(self.width, self.height, filter),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub enum Packaging {
+    #[default]
+    Zip,
+    Multipart,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct Renditions {
+    #[validate(length(min = 1, max = 16))]
+    #[validate]
+    pub sizes: Vec<Rendition>,
+    #[serde(default)]
+    pub packaging: Packaging,
+}
+
+pub struct Packaged {
+    pub bytes: Vec<u8>,
+    pub content_type: String,
+    pub negotiated: bool,
+}
+
+impl From<Encoded> for Packaged {
+    fn from(encoded: Encoded) -> Self {
+        Packaged {
+            content_type: encoded.format.content_type().to_string(),
+            bytes: encoded.bytes,
+            negotiated: encoded.negotiated,
+        }
+    }
+}
+
+impl IntoResponse for Packaged {
+    fn into_response(self) -> Response {
+        let mut response =
+            ([(header::CONTENT_TYPE, self.content_type)], self.bytes).into_response();
+        // caches must not hand a body negotiated for one client to another
+        if self.negotiated {
+            response
+                .headers_mut()
+                .insert(header::VARY, HeaderValue::from_static("Accept"));
+        }
+        response
+    }
+}
+
+// resizes the rendered cover into every rendition and bundles them in one body
+pub fn package_renditions(
+    image: &DynamicImage,
+    renditions: &Renditions,
+    options: &OutputOptions,
+    accept: Option<&str>,
+) -> Result<Packaged, AppError> {
+    let mut files = Vec::new();
+    for rendition in &renditions.sizes {
+        let encoded = encode_with_options(&rendition.resize(image), options, accept)?;
+        files.push((rendition.file_name(encoded.format), encoded));
+    }
+
+    match renditions.packaging {
+        Packaging::Zip => {
+            let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+            let file_options =
+                SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+            for (name, encoded) in files {
+                zip.start_file(name, file_options)
+                    .map_err(|e| anyhow!("zip error: {}", e))?;
+                zip.write_all(&encoded.bytes)?;
+            }
+            let bytes = zip
+                .finish()
+                .map_err(|e| anyhow!("zip error: {}", e))?
+                .into_inner();
+            Ok(Packaged {
+                bytes,
+                content_type: "application/zip".to_string(),
+                negotiated: options.format.is_none(),
+            })
+        }
+        Packaging::Multipart => {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default();
+            let boundary = format!("litcovers-{:x}", nanos);
+            let mut bytes = Vec::new();
+            for (name, encoded) in files {
+                write!(
+                    bytes,
+                    "--{}\r\nContent-Type: {}\r\nContent-Disposition: attachment; filename=\"{}\"\r\n\r\n",
+                    boundary,
+                    encoded.format.content_type(),
+                    name
+                )?;
+                bytes.extend_from_slice(&encoded.bytes);
+                bytes.extend_from_slice(b"\r\n");
+            }
+            write!(bytes, "--{}--\r\n", boundary)?;
+            Ok(Packaged {
+                bytes,
+                content_type: format!("multipart/mixed; boundary={}", boundary),
+                negotiated: options.format.is_none(),
+            })
+        }
+    }
+}
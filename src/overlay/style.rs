@@ -0,0 +1,281 @@
+use std::sync::Arc;
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "ColorRepr", into = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const WHITE: Color = Color::rgba(255, 255, 255, 255);
+    pub const BLACK: Color = Color::rgba(0, 0, 0, 255);
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        (self.r, self.g, self.b)
+    }
+
+    // alpha channel as 0..1
+    pub fn opacity(&self) -> f32 {
+        self.a as f32 / 255.0
+    }
+
+    // parses "#rgb", "#rrggbb" and "#rrggbbaa", the leading '#' is optional
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let hex = hex.trim().trim_start_matches('#');
+        if !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        match hex.len() {
+            3 => {
+                let short = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok().map(|v| v * 17);
+                Some(Color::rgba(short(0)?, short(1)?, short(2)?, 255))
+            }
+            6 => Some(Color::rgba(channel(0)?, channel(2)?, channel(4)?, 255)),
+            8 => Some(Color::rgba(
+                channel(0)?,
+                channel(2)?,
+                channel(4)?,
+                channel(6)?,
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorRepr {
+    Hex(String),
+    Rgb([u8; 3]),
+    Rgba([u8; 4]),
+}
+
+impl TryFrom<ColorRepr> for Color {
+    type Error = String;
+
+    fn try_from(repr: ColorRepr) -> Result<Self, Self::Error> {
+        match repr {
+            ColorRepr::Hex(hex) => {
+                Color::from_hex(&hex).ok_or_else(|| format!("invalid hex color: {}", hex))
+            }
+            ColorRepr::Rgb([r, g, b]) => Ok(Color::rgba(r, g, b, 255)),
+            ColorRepr::Rgba([r, g, b, a]) => Ok(Color::rgba(r, g, b, a)),
+        }
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        format!(
+            "#{:02x}{:02x}{:02x}{:02x}",
+            color.r, color.g, color.b, color.a
+        )
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Stroke {
+    pub color: Color,
+    // outline width in pixels
+    pub width: f32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Shadow {
+    #[serde(default = "default_shadow_offset")]
+    pub offset: (i32, i32),
+    #[serde(default)]
+    pub blur: f32,
+    #[serde(default = "default_shadow_color")]
+    pub color: Color,
+}
+
+fn default_shadow_offset() -> (i32, i32) {
+    (2, 2)
+}
+
+fn default_shadow_color() -> Color {
+    Color::rgba(0, 0, 0, 160)
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ColorStop {
+    // position along the gradient, 0..1
+    pub offset: f32,
+    pub color: Color,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Fill {
+    // angle in degrees, 0 runs left to right and 90 top to bottom
+    Linear {
+        #[serde(default = "default_angle")]
+        angle: f32,
+        stops: Vec<ColorStop>,
+    },
+    // center is normalized to the text box, radius is a fraction of its longer side
+    Radial {
+        #[serde(default = "default_center")]
+        center: (f32, f32),
+        #[serde(default = "default_radius")]
+        radius: f32,
+        stops: Vec<ColorStop>,
+    },
+    // image stretched to cover the text box, loaded by the handler
+    Texture {
+        url: String,
+        #[serde(skip)]
+        image: Option<Arc<DynamicImage>>,
+    },
+}
+
+fn default_angle() -> f32 {
+    90.0
+}
+
+fn default_center() -> (f32, f32) {
+    (0.5, 0.5)
+}
+
+fn default_radius() -> f32 {
+    0.5
+}
+
+impl Fill {
+    // returns the fill color at (x, y) inside a text box of the given size
+    pub fn painter(&self, width: u32, height: u32) -> Box<dyn Fn(f32, f32) -> Color + '_> {
+        let (w, h) = (width.max(1) as f32, height.max(1) as f32);
+        match self {
+            Fill::Linear { angle, stops } => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                let length = cos.abs() * w + sin.abs() * h;
+                Box::new(move |x, y| {
+                    let t = ((x - w / 2.0) * cos + (y - h / 2.0) * sin) / length + 0.5;
+                    gradient_at(stops, t)
+                })
+            }
+            Fill::Radial {
+                center,
+                radius,
+                stops,
+            } => {
+                let (cx, cy) = (center.0 * w, center.1 * h);
+                let r = (radius * w.max(h)).max(1.0);
+                Box::new(move |x, y| gradient_at(stops, (x - cx).hypot(y - cy) / r))
+            }
+            Fill::Texture { image, .. } => match image {
+                Some(image) => {
+                    let texture =
+                        image.resize_to_fill(width.max(1), height.max(1), FilterType::Lanczos3);
+                    let (tw, th) = texture.dimensions();
+                    Box::new(move |x, y| {
+                        let x = (x.max(0.0) as u32).min(tw - 1);
+                        let y = (y.max(0.0) as u32).min(th - 1);
+                        let p = texture.get_pixel(x, y);
+                        Color::rgba(p[0], p[1], p[2], p[3])
+                    })
+                }
+                None => Box::new(|_, _| Color::WHITE),
+            },
+        }
+    }
+}
+
+// interpolates between the stops surrounding t
+fn gradient_at(stops: &[ColorStop], t: f32) -> Color {
+    let (first, last) = match (stops.first(), stops.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Color::WHITE,
+    };
+    if t <= first.offset {
+        return first.color;
+    }
+    for pair in stops.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+        if t <= to.offset {
+            let span = to.offset - from.offset;
+            let k = if span > 0.0 {
+                (t - from.offset) / span
+            } else {
+                1.0
+            };
+            let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * k).round() as u8;
+            return Color::rgba(
+                lerp(from.color.r, to.color.r),
+                lerp(from.color.g, to.color.g),
+                lerp(from.color.b, to.color.b),
+                lerp(from.color.a, to.color.a),
+            );
+        }
+    }
+    last.color
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TextStyle {
+    #[serde(default = "default_color")]
+    pub color: Color,
+    // replaces the flat color when set
+    #[serde(default)]
+    pub fill: Option<Fill>,
+    #[serde(default)]
+    pub stroke: Option<Stroke>,
+    #[serde(default)]
+    pub shadow: Option<Shadow>,
+    // look of the *marked* spans, the markup is only read when this is set
+    #[serde(default)]
+    pub emphasis: Option<Emphasis>,
+}
+
+fn default_color() -> Color {
+    Color::WHITE
+}
+
+// unset parts follow the rest of the block
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Emphasis {
+    // fallbacks of the block still apply
+    #[serde(default)]
+    pub font: Option<String>,
+    // relative to the size of the line
+    #[serde(default = "default_emphasis_size")]
+    pub size: f32,
+    #[serde(default)]
+    pub color: Option<Color>,
+}
+
+fn default_emphasis_size() -> f32 {
+    1.0
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            color: default_color(),
+            fill: None,
+            stroke: None,
+            shadow: None,
+            emphasis: None,
+        }
+    }
+}
+
+impl TextStyle {
+    // extra pixels around the glyphs that stroke and shadow may paint
+    pub fn margin(&self) -> i32 {
+        let stroke = self.stroke.as_ref().map_or(0.0, |s| s.width.max(0.0));
+        let blur = self.shadow.as_ref().map_or(0.0, |s| s.blur.max(0.0));
+        (stroke + blur).ceil() as i32 + 1
+    }
+}
@@ -0,0 +1,323 @@
+use std::sync::OnceLock;
+
+use hyphenation::{Hyphenator, Language, Load, Standard};
+use rusttype::Scale;
+use textwrap::core::Fragment;
+use textwrap::wrap_algorithms::{wrap_optimal_fit, Penalties};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::chain::FontChain;
+
+// widths are counted in average characters of the font, the unit the penalties are tuned for
+const PENALTIES: Penalties = Penalties {
+    nline_penalty: 1000,
+    overflow_penalty: 50 * 50,
+    // a last line under half the measure holding a single word is an orphan
+    short_last_line_fraction: 2,
+    short_last_line_penalty: 400,
+    // words are only hyphenated when they do not fit the measure alone
+    hyphen_penalty: 150,
+};
+
+// wider measures tried to take an orphan back onto the line above
+const ORPHAN_RETRIES: usize = 8;
+
+// a word, or a syllable of one when hyphenating, measured in the font
+#[derive(Debug)]
+struct Piece<'t> {
+    text: &'t str,
+    width: f64,
+    // space after the last piece of a word
+    whitespace: f64,
+    // hyphen drawn when a line breaks after a piece inside a word
+    hyphen: f64,
+}
+
+impl Fragment for Piece<'_> {
+    fn width(&self) -> f64 {
+        self.width
+    }
+
+    fn whitespace_width(&self) -> f64 {
+        self.whitespace
+    }
+
+    fn penalty_width(&self) -> f64 {
+        self.hyphen
+    }
+}
+
+// wraps text by its measured width in the font, about line_length average characters a line,
+// with the lines evened out and no short word left alone on the last one,
+// line breaks in the text are kept
+pub fn wrap_balanced(
+    text: &str,
+    font: &FontChain,
+    line_length: usize,
+    hyphenate: bool,
+) -> Vec<String> {
+    let unit = average_char_width(text, font);
+    let target = line_length as f64;
+    let paragraphs = measure_paragraphs(text, font, unit, hyphenate.then_some(target));
+    wrap_measured(text, &paragraphs, target, font.marks_emphasis())
+}
+
+// the words of a text measured once, for wrapping it at many line lengths,
+// they are never hyphenated since that depends on the line length
+pub struct MeasuredText<'t> {
+    text: &'t str,
+    paragraphs: Vec<Vec<Piece<'t>>>,
+    marks: bool,
+}
+
+impl<'t> MeasuredText<'t> {
+    pub fn new(text: &'t str, font: &FontChain) -> MeasuredText<'t> {
+        let unit = average_char_width(text, font);
+        MeasuredText {
+            text,
+            paragraphs: measure_paragraphs(text, font, unit, None),
+            marks: font.marks_emphasis(),
+        }
+    }
+
+    // same lines as wrap_balanced without hyphenation
+    pub fn wrap(&self, line_length: usize) -> Vec<String> {
+        wrap_measured(self.text, &self.paragraphs, line_length as f64, self.marks)
+    }
+}
+
+fn measure_paragraphs<'t>(
+    text: &'t str,
+    font: &FontChain,
+    unit: f64,
+    measure_limit: Option<f64>,
+) -> Vec<Vec<Piece<'t>>> {
+    let mut open = false;
+    paragraphs(text)
+        .map(|paragraph| {
+            let pieces = pieces(paragraph, font, unit, measure_limit, open);
+            open ^= font.marks_emphasis() && paragraph.matches('*').count() % 2 == 1;
+            pieces
+        })
+        .collect()
+}
+
+fn wrap_measured(text: &str, paragraphs: &[Vec<Piece>], target: f64, marks: bool) -> Vec<String> {
+    let lines = paragraphs
+        .iter()
+        .flat_map(|pieces| wrap_paragraph(pieces, target))
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        return vec![text.to_string()];
+    }
+    match marks {
+        true => close_spans(lines),
+        false => lines,
+    }
+}
+
+// the lines the text was broken into, with emphasis closed on each line when the font marks it
+pub fn hard_lines(text: &str, font: &FontChain) -> Vec<String> {
+    let lines = paragraphs(text).map(String::from).collect::<Vec<_>>();
+    if lines.is_empty() {
+        return vec![text.to_string()];
+    }
+    match font.marks_emphasis() {
+        true => close_spans(lines),
+        false => lines,
+    }
+}
+
+fn paragraphs(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+}
+
+fn wrap_paragraph(pieces: &[Piece], target: f64) -> Vec<String> {
+    // lines are scaled to fit later, so the measure may grow a little to take an orphan back,
+    // in a few steps however long the measure is
+    let step = (target * 0.3 / ORPHAN_RETRIES as f64).max(0.5);
+    let mut width = target;
+    while width <= target * 1.3 {
+        let lines = balance(pieces, width);
+        if !orphaned(&lines) {
+            return join(lines);
+        }
+        width += step;
+    }
+    join(balance(pieces, target))
+}
+
+// ends a span left open at the end of a line and opens it again on the next,
+// so every line parses on its own
+fn close_spans(lines: Vec<String>) -> Vec<String> {
+    let mut open = false;
+    lines
+        .into_iter()
+        .map(|line| {
+            let mut line = match open {
+                true => format!("*{}", line),
+                false => line,
+            };
+            open = line.matches('*').count() % 2 == 1;
+            if open {
+                line.push('*');
+            }
+            line
+        })
+        .collect()
+}
+
+// narrows the measure as far as the line count allows so the lines come out even
+fn balance<'a, 't>(pieces: &'a [Piece<'t>], width: f64) -> Vec<&'a [Piece<'t>]> {
+    let count = fit(pieces, width).len();
+    if count < 2 {
+        return fit(pieces, width);
+    }
+    let widest = pieces.iter().map(|piece| piece.width).fold(0.0, f64::max);
+    let (mut low, mut high) = (widest.min(width), width);
+    for _ in 0..16 {
+        let middle = (low + high) / 2.0;
+        if fit(pieces, middle).len() <= count {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    fit(pieces, high)
+}
+
+fn fit<'a, 't>(pieces: &'a [Piece<'t>], width: f64) -> Vec<&'a [Piece<'t>]> {
+    wrap_optimal_fit(pieces, &[width], &PENALTIES).unwrap_or_else(|_| vec![pieces])
+}
+
+// a last line holding one word under half as wide as the line above it
+fn orphaned(lines: &[&[Piece]]) -> bool {
+    match lines {
+        [.., previous, last] => {
+            let width: f64 = previous
+                .iter()
+                .map(|piece| piece.width + piece.whitespace)
+                .sum();
+            last.len() == 1 && previous.len() > 1 && last[0].width * 2.0 < width
+        }
+        _ => false,
+    }
+}
+
+fn join(lines: Vec<&[Piece]>) -> Vec<String> {
+    lines
+        .into_iter()
+        .map(|line| {
+            let mut text = String::new();
+            for (i, piece) in line.iter().enumerate() {
+                text.push_str(piece.text);
+                let last = i + 1 == line.len();
+                if piece.whitespace > 0.0 && !last {
+                    text.push(' ');
+                }
+                if piece.hyphen > 0.0 && last {
+                    text.push('-');
+                }
+            }
+            text
+        })
+        .collect()
+}
+
+fn average_char_width(text: &str, font: &FontChain) -> f64 {
+    let marks = font.marks_emphasis();
+    let letters = text
+        .graphemes(true)
+        // markers of emphasized spans are not drawn
+        .filter(|g| !g.trim().is_empty() && (!marks || *g != "*"))
+        .count();
+    let width = font.advance_width(
+        &text.split_whitespace().collect::<String>(),
+        Scale::uniform(1.0),
+    );
+    match letters > 0 && width > 0.0 {
+        true => width as f64 / letters as f64,
+        false => 1.0,
+    }
+}
+
+// splits text into words, hyphenating the ones wider than the measure when one is given,
+// open tells whether the text starts inside an emphasized span
+fn pieces<'t>(
+    text: &'t str,
+    font: &FontChain,
+    unit: f64,
+    measure_limit: Option<f64>,
+    mut open: bool,
+) -> Vec<Piece<'t>> {
+    let plain = |text: &str| font.advance_width(text, Scale::uniform(1.0)) as f64 / unit;
+    let space = plain(" ");
+    let hyphen = plain("-");
+    let marks = font.marks_emphasis();
+    let mut pieces = Vec::new();
+    // a piece inside a span is measured as emphasized
+    let measure = |text: &str, open: bool| match open {
+        true => plain(&format!("*{}", text)),
+        false => plain(text),
+    };
+    for word in text.split_whitespace() {
+        // words that already carry a hyphen break after it and nowhere else
+        let breaks = if word.contains('-') {
+            word.match_indices('-')
+                .map(|(i, _)| i + 1)
+                .filter(|&i| i < word.len())
+                .collect()
+        } else if measure_limit.is_some_and(|limit| measure(word, open) > limit) {
+            hyphenation_points(word)
+        } else {
+            Vec::new()
+        };
+        let mut start = 0;
+        for end in breaks.into_iter().chain([word.len()]) {
+            let last = end == word.len();
+            let syllable = &word[start..end];
+            let width = measure(syllable, open);
+            open ^= marks && syllable.matches('*').count() % 2 == 1;
+            pieces.push(Piece {
+                text: syllable,
+                width,
+                whitespace: if last { space } else { 0.0 },
+                hyphen: if last || syllable.ends_with('-') {
+                    0.0
+                } else {
+                    hyphen
+                },
+            });
+            start = end;
+        }
+    }
+    pieces
+}
+
+// the hyphenation crate only embeds its english dictionary, this is its russian one
+const RUSSIAN_DICTIONARY: &[u8] = include_bytes!("../../dictionaries/ru.standard.bincode");
+
+// byte offsets where the word may be hyphenated, english and russian words are known
+fn hyphenation_points(word: &str) -> Vec<usize> {
+    static ENGLISH: OnceLock<Option<Standard>> = OnceLock::new();
+    static RUSSIAN: OnceLock<Option<Standard>> = OnceLock::new();
+
+    let dictionary = if word
+        .chars()
+        .any(|c| matches!(c, 'а'..='я' | 'А'..='Я' | 'ё' | 'Ё'))
+    {
+        RUSSIAN.get_or_init(|| {
+            Standard::from_reader(Language::Russian, &mut &RUSSIAN_DICTIONARY[..]).ok()
+        })
+    } else if word.chars().any(|c| c.is_ascii_alphabetic()) {
+        ENGLISH.get_or_init(|| Standard::from_embedded(Language::EnglishUS).ok())
+    } else {
+        &None
+    };
+    match dictionary {
+        Some(dictionary) => dictionary.hyphenate(word).breaks,
+        None => Vec::new(),
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use validator::Validate;
+
+use crate::{
+    error::AppError,
+    overlay::{
+        handlers::{check_cover, render_cover, BookCoverParams},
+        output::Packaged,
+    },
+    router::AppState,
+};
+
+use super::ReplicateClient;
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct GenerateParams {
+    #[validate(length(min = 1))]
+    pub prompt: String,
+    #[validate(length(min = 1))]
+    pub version: String,
+    // extra model inputs sent along with the prompt
+    #[serde(default)]
+    pub input: Map<String, Value>,
+    // overlay params, image_url is filled from the prediction output
+    pub cover: BookCoverParams,
+}
+
+#[axum_macros::debug_handler]
+pub async fn generate(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<GenerateParams>,
+) -> Result<Packaged, AppError> {
+    payload.validate()?;
+    // predictions are paid for, so the cover has to be renderable before one is made
+    check_cover(&payload.cover, &state)?;
+
+    let mut input = payload.input;
+    input.insert("prompt".to_string(), Value::String(payload.prompt));
+    let image_url = ReplicateClient::new(&state.settings)?
+        .run(&payload.version, &input)
+        .await?;
+
+    let mut cover = payload.cover;
+    cover.image_url = image_url;
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    render_cover(cover, state, accept, None).await
+}
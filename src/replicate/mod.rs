@@ -0,0 +1,135 @@
+pub mod handlers;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{error::AppError, settings::Settings};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PredictionStatus {
+    Starting,
+    Processing,
+    Succeeded,
+    Failed,
+    Canceled,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Prediction {
+    pub id: String,
+    pub status: PredictionStatus,
+    #[serde(default)]
+    pub output: Option<Value>,
+    #[serde(default)]
+    pub error: Option<Value>,
+}
+
+impl Prediction {
+    // first image URL of the output, models return either a list or a single URL
+    pub fn output_url(&self) -> Option<String> {
+        match self.output.as_ref()? {
+            Value::String(url) => Some(url.clone()),
+            Value::Array(urls) => urls.iter().find_map(|url| url.as_str().map(String::from)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CreatePrediction<'a> {
+    version: &'a str,
+    input: &'a Map<String, Value>,
+}
+
+pub struct ReplicateClient<'a> {
+    settings: &'a Settings,
+    http: reqwest::Client,
+}
+
+impl<'a> ReplicateClient<'a> {
+    pub fn new(settings: &'a Settings) -> Result<Self, AppError> {
+        let http = reqwest::Client::builder()
+            .connect_timeout(settings.fetch_connect_timeout)
+            .timeout(settings.replicate_request_timeout)
+            .build()?;
+        Ok(ReplicateClient { settings, http })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/v1/{}",
+            self.settings.replicate_base_url.trim_end_matches('/'),
+            path
+        )
+    }
+
+    pub async fn create_prediction(
+        &self,
+        version: &str,
+        input: &Map<String, Value>,
+    ) -> Result<Prediction, AppError> {
+        let prediction = self
+            .http
+            .post(self.url("predictions"))
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("Token {}", self.settings.replicate_token),
+            )
+            .json(&CreatePrediction { version, input })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(prediction)
+    }
+
+    pub async fn get_prediction(&self, id: &str) -> Result<Prediction, AppError> {
+        let prediction = self
+            .http
+            .get(self.url(&format!("predictions/{}", id)))
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("Token {}", self.settings.replicate_token),
+            )
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(prediction)
+    }
+
+    // creates a prediction and polls it until it produces an image URL
+    pub async fn run(&self, version: &str, input: &Map<String, Value>) -> Result<String, AppError> {
+        let poll = async {
+            let mut prediction = self.create_prediction(version, input).await?;
+            loop {
+                match prediction.status {
+                    PredictionStatus::Succeeded => {
+                        return prediction.output_url().ok_or_else(|| {
+                            AppError::GenerationFailed("prediction has no image output".into())
+                        });
+                    }
+                    PredictionStatus::Failed | PredictionStatus::Canceled => {
+                        let reason = prediction
+                            .error
+                            .map(|e| e.to_string())
+                            .unwrap_or_else(|| format!("{:?}", prediction.status));
+                        return Err(AppError::GenerationFailed(reason));
+                    }
+                    PredictionStatus::Starting | PredictionStatus::Processing => {
+                        tokio::time::sleep(self.settings.replicate_poll_interval).await;
+                        prediction = self.get_prediction(&prediction.id).await?;
+                    }
+                }
+            }
+        };
+        match tokio::time::timeout(self.settings.replicate_timeout, poll).await {
+            Ok(Err(AppError::ReqwestError(e))) if e.is_timeout() => Err(AppError::Timeout),
+            Ok(result) => result,
+            Err(_) => Err(AppError::Timeout),
+        }
+    }
+}
@@ -1,40 +1,100 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::sync::Arc;
 
 use axum::{
-    extract::State,
+    extract::{DefaultBodyLimit, State},
     http::StatusCode,
     routing::{get, post},
-    Router,
+    Json, Router,
 };
 
-use crate::{overlay::handlers::book_cover, settings::get_config};
+use image::DynamicImage;
+use tokio::sync::Semaphore;
+
+use crate::{
+    cache::{CacheStats, DiskCache, ImageCache, InFlight},
+    error::AppError,
+    fonts::{
+        handlers::{font_preview, list_fonts},
+        registry::FontRegistry,
+    },
+    jobs::{
+        handlers::{create_job, job_status},
+        JobStore,
+    },
+    overlay::handlers::{book_cover, book_cover_batch, validate_cover},
+    replicate::handlers::generate,
+    settings::{get_config, Settings},
+};
 
-#[derive(Debug)]
 pub struct AppState {
-    pub images: Mutex<HashMap<String, Vec<u8>>>,
+    pub images: ImageCache,
+    pub disk_images: Option<DiskCache>,
+    pub fonts: Arc<FontRegistry>,
+    pub downloads: InFlight<Result<Arc<DynamicImage>, Arc<AppError>>>,
+    pub settings: Settings,
+    pub jobs: JobStore,
+    pub job_workers: Semaphore,
+    // shared by every batch and job so their covers never exceed batch_concurrency together
+    pub render_workers: Arc<Semaphore>,
 }
 
 pub fn app() -> Router {
-    let app_state = Arc::new(AppState {
-        images: Mutex::new(HashMap::new()),
-    });
+    app_with_settings(get_config())
+}
+
+pub fn app_with_settings(settings: Settings) -> Router {
+    app_with_state(state_with_settings(settings))
+}
+
+// needs no runtime, the font directory is only rescanned once the caller starts
+// `fonts.watch()` from inside one
+pub fn state_with_settings(settings: Settings) -> Arc<AppState> {
+    let fonts = Arc::new(FontRegistry::load(
+        &settings.fonts_dir,
+        settings.font_reload_interval,
+    ));
+    Arc::new(AppState {
+        images: ImageCache::new(
+            settings.image_cache_max_bytes,
+            settings.image_cache_ttl,
+            settings.image_cache_decoded,
+        ),
+        disk_images: settings
+            .image_disk_cache_dir
+            .as_ref()
+            .map(|dir| DiskCache::new(dir, settings.image_disk_cache_max_bytes)),
+        downloads: InFlight::default(),
+        fonts,
+        jobs: JobStore::default(),
+        job_workers: Semaphore::new(settings.job_concurrency.max(1)),
+        render_workers: Arc::new(Semaphore::new(settings.batch_concurrency.max(1))),
+        settings,
+    })
+}
+
+// the routes over a state the caller keeps a handle on
+pub fn app_with_state(app_state: Arc<AppState>) -> Router {
+    let upload_limit = app_state.settings.upload_max_bytes / 3 * 4 + 1024 * 1024;
     Router::new()
         .route("/health_check", get(health_check))
-        .route("/overlay", post(book_cover))
+        .route(
+            "/overlay",
+            // room for the base64 or multipart overhead around an uploaded image
+            post(book_cover).layer(DefaultBodyLimit::max(upload_limit)),
+        )
+        .route("/overlay/batch", post(book_cover_batch))
+        .route("/overlay/validate", post(validate_cover))
+        .route("/generate", post(generate))
+        .route("/jobs", post(create_job))
+        .route("/jobs/:id", get(job_status))
+        .route("/fonts", get(list_fonts))
+        .route("/fonts/:id/preview", get(font_preview))
         .route("/state", get(state_view))
         .with_state(app_state)
-        .with_state(get_config().clone())
 }
 
-async fn state_view(State(state): State<Arc<AppState>>) -> StatusCode {
-    let images = state.images.lock().unwrap();
-    for (url, _bytes) in images.iter() {
-        println!("url: {}", url);
-    }
-    StatusCode::OK
+async fn state_view(State(state): State<Arc<AppState>>) -> Json<CacheStats> {
+    Json(state.images.stats())
 }
 
 async fn health_check() -> StatusCode {
@@ -1,10 +1,158 @@
-#[derive(Clone)]
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+#[derive(Clone, Debug)]
 pub struct Settings {
     pub replicate_token: String,
+    pub replicate_base_url: String,
+    pub replicate_poll_interval: Duration,
+    // give up on a prediction that is not done by then
+    pub replicate_timeout: Duration,
+    // longest wait for a single call to the Replicate API
+    pub replicate_request_timeout: Duration,
+    // how many batch and job covers are rendered at the same time, across all requests
+    pub batch_concurrency: usize,
+    pub max_batch_size: usize,
+    // how many render jobs run at the same time, the rest wait queued
+    pub job_concurrency: usize,
+    // jobs waiting or running at once, more are refused until some finish
+    pub max_queued_jobs: usize,
+    pub job_timeout: Duration,
+    // how long finished jobs stay available for polling
+    pub job_ttl: Duration,
+    // key for signing webhook payloads, unsigned when empty
+    pub webhook_secret: String,
+    // hosts webhooks may be sent to, any public host when empty
+    pub webhook_allowed_hosts: Vec<String>,
+    pub webhook_timeout: Duration,
+    pub image_cache_max_bytes: usize,
+    pub image_cache_ttl: Duration,
+    // keep decoded pixels next to the downloaded bytes so repeat renders skip decoding
+    pub image_cache_decoded: bool,
+    // disk tier is disabled when no directory is set
+    pub image_disk_cache_dir: Option<PathBuf>,
+    pub image_disk_cache_max_bytes: u64,
+    // hosts background images may be fetched from, any public host when empty
+    pub fetch_allowed_hosts: Vec<String>,
+    // lets image and webhook URLs reach loopback and private networks, for local testing only
+    pub fetch_allow_private: bool,
+    pub fetch_max_bytes: usize,
+    pub fetch_connect_timeout: Duration,
+    // longest wait for the response or for the next chunk of the body
+    pub fetch_read_timeout: Duration,
+    // longest time for the whole body, however steadily it trickles in
+    pub fetch_body_timeout: Duration,
+    // largest background image accepted as an upload or base64 field
+    pub upload_max_bytes: usize,
+    pub fonts_dir: PathBuf,
+    // how often the fonts directory is checked for added or changed files
+    pub font_reload_interval: Duration,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            replicate_token: String::new(),
+            replicate_base_url: "https://api.replicate.com".to_string(),
+            replicate_poll_interval: Duration::from_secs(1),
+            replicate_timeout: Duration::from_secs(120),
+            replicate_request_timeout: Duration::from_secs(30),
+            batch_concurrency: 4,
+            max_batch_size: 50,
+            job_concurrency: 2,
+            max_queued_jobs: 100,
+            job_timeout: Duration::from_secs(300),
+            job_ttl: Duration::from_secs(3600),
+            webhook_secret: String::new(),
+            webhook_allowed_hosts: Vec::new(),
+            webhook_timeout: Duration::from_secs(10),
+            image_cache_max_bytes: 256 * 1024 * 1024,
+            image_cache_ttl: Duration::from_secs(120),
+            image_cache_decoded: true,
+            image_disk_cache_dir: None,
+            image_disk_cache_max_bytes: 1024 * 1024 * 1024,
+            fetch_allowed_hosts: Vec::new(),
+            fetch_allow_private: false,
+            fetch_max_bytes: 32 * 1024 * 1024,
+            fetch_connect_timeout: Duration::from_secs(5),
+            fetch_read_timeout: Duration::from_secs(30),
+            fetch_body_timeout: Duration::from_secs(60),
+            upload_max_bytes: 32 * 1024 * 1024,
+            fonts_dir: PathBuf::from("fonts"),
+            font_reload_interval: Duration::from_secs(5),
+        }
+    }
 }
 
 pub fn get_config() -> Settings {
     dotenvy::dotenv().ok();
     let replicate_token = dotenvy::var("REPLICATE_TOKEN").expect("REPLICATE_TOKEN must be set");
-    Settings { replicate_token }
+    let defaults = Settings::default();
+    Settings {
+        replicate_token,
+        replicate_base_url: var_or("REPLICATE_BASE_URL", defaults.replicate_base_url),
+        replicate_poll_interval: Duration::from_millis(var_or(
+            "REPLICATE_POLL_MS",
+            defaults.replicate_poll_interval.as_millis() as u64,
+        )),
+        replicate_timeout: secs_or("REPLICATE_TIMEOUT_SECS", defaults.replicate_timeout),
+        replicate_request_timeout: secs_or(
+            "REPLICATE_REQUEST_TIMEOUT_SECS",
+            defaults.replicate_request_timeout,
+        ),
+        batch_concurrency: var_or("BATCH_CONCURRENCY", defaults.batch_concurrency),
+        max_batch_size: var_or("MAX_BATCH_SIZE", defaults.max_batch_size),
+        job_concurrency: var_or("JOB_CONCURRENCY", defaults.job_concurrency),
+        max_queued_jobs: var_or("MAX_QUEUED_JOBS", defaults.max_queued_jobs),
+        job_timeout: secs_or("JOB_TIMEOUT_SECS", defaults.job_timeout),
+        job_ttl: secs_or("JOB_TTL_SECS", defaults.job_ttl),
+        webhook_secret: var_or("WEBHOOK_SECRET", defaults.webhook_secret),
+        webhook_allowed_hosts: hosts_or("WEBHOOK_ALLOWED_HOSTS", defaults.webhook_allowed_hosts),
+        webhook_timeout: secs_or("WEBHOOK_TIMEOUT_SECS", defaults.webhook_timeout),
+        image_cache_max_bytes: var_or("IMAGE_CACHE_MAX_BYTES", defaults.image_cache_max_bytes),
+        image_cache_ttl: secs_or("IMAGE_CACHE_TTL_SECS", defaults.image_cache_ttl),
+        image_cache_decoded: var_or("IMAGE_CACHE_DECODED", defaults.image_cache_decoded),
+        image_disk_cache_dir: dotenvy::var("IMAGE_DISK_CACHE_DIR").ok().map(PathBuf::from),
+        image_disk_cache_max_bytes: var_or(
+            "IMAGE_DISK_CACHE_MAX_BYTES",
+            defaults.image_disk_cache_max_bytes,
+        ),
+        fetch_allowed_hosts: hosts_or("FETCH_ALLOWED_HOSTS", defaults.fetch_allowed_hosts),
+        fetch_allow_private: var_or("FETCH_ALLOW_PRIVATE", defaults.fetch_allow_private),
+        fetch_max_bytes: var_or("FETCH_MAX_BYTES", defaults.fetch_max_bytes),
+        fetch_connect_timeout: secs_or(
+            "FETCH_CONNECT_TIMEOUT_SECS",
+            defaults.fetch_connect_timeout,
+        ),
+        fetch_read_timeout: secs_or("FETCH_READ_TIMEOUT_SECS", defaults.fetch_read_timeout),
+        fetch_body_timeout: secs_or("FETCH_BODY_TIMEOUT_SECS", defaults.fetch_body_timeout),
+        upload_max_bytes: var_or("UPLOAD_MAX_BYTES", defaults.upload_max_bytes),
+        fonts_dir: var_or("FONTS_DIR", defaults.fonts_dir),
+        font_reload_interval: secs_or("FONT_RELOAD_SECS", defaults.font_reload_interval),
+    }
+}
+
+// reads an optional variable, falling back to the default when unset or invalid
+fn var_or<T: FromStr>(key: &str, default: T) -> T {
+    dotenvy::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn secs_or(key: &str, default: Duration) -> Duration {
+    Duration::from_secs(var_or(key, default.as_secs()))
+}
+
+// a comma separated list of host names
+fn hosts_or(key: &str, default: Vec<String>) -> Vec<String> {
+    dotenvy::var(key)
+        .map(|hosts| {
+            hosts
+                .split(',')
+                .map(str::trim)
+                .filter(|host| !host.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or(default)
 }
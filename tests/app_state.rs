@@ -6,7 +6,9 @@ use axum::{
 };
 use litcovers_api::{
     overlay::handlers::BookCoverParams,
-    overlay::image::{BlendMode, PositionType},
+    overlay::image::{BlendMode, Direction, PositionType, WritingMode},
+    overlay::output::OutputOptions,
+    overlay::style::TextStyle,
     router::app,
 };
 use tower::ServiceExt;
@@ -18,13 +20,37 @@ async fn req_from_state_is_faster() {
         author_font: "Stig.ttf".to_string(),
         author: "Prison Mike".to_string(),
         author_position: PositionType::TopCenter,
+        author_direction: Direction::Auto,
+        author_writing_mode: WritingMode::Horizontal,
+        author_rotation: 0.0,
+        author_arc_radius: None,
+        author_uppercase: None,
         title_font: "Stig.ttf".to_string(),
         title: "Harry Potter and other people".to_string(),
         title_position: PositionType::BottomCenter,
+        title_direction: Direction::Auto,
+        title_writing_mode: WritingMode::Horizontal,
+        title_rotation: 0.0,
+        title_arc_radius: None,
+        title_uppercase: None,
+        fallback_fonts: Vec::new(),
+        author_fallback_fonts: None,
+        title_fallback_fonts: None,
         blend_mode: BlendMode::Overlay,
+        author_blend_mode: None,
+        title_blend_mode: None,
         alfa: 3.0,
         image_url: "https://replicate.delivery/pbxt/pX5B4V8QzvKFBBk7CHm788FQZKeQXvO8RbhfGNLXpIbYcZUQA/out-0.png".to_string(),
+        image_base64: None,
+        image_upload: None,
         line_length: 16,
+        hyphenate: true,
+        author_style: TextStyle::default(),
+        title_style: TextStyle::default(),
+        output: OutputOptions::default(),
+        renditions: None,
+        author_max_height: 0.08,
+        title_max_height: 0.3,
     };
 
     let start = Instant::now();
@@ -0,0 +1,132 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::{http::StatusCode, Router};
+use image::{DynamicImage, GenericImageView};
+use litcovers_api::{
+    fonts::registry::FontRegistry, overlay::mask::Mask, router::app_with_settings,
+    settings::Settings,
+};
+use rusttype::{point, Scale};
+use serde_json::{json, Value};
+
+#[test]
+fn unturned_glyphs_rasterize_like_plain_ones() {
+    let registry = FontRegistry::load("fonts", Duration::from_secs(60));
+    let font = registry.get("Stig.ttf").unwrap().font.clone();
+    let glyphs = font
+        .layout("Seal", Scale::uniform(48.0), point(10.3, 60.7))
+        .collect::<Vec<_>>();
+    let plain = Mask::from_glyphs(&glyphs, 2).unwrap();
+    let turned = glyphs.iter().map(|g| (g.clone(), 0.0)).collect::<Vec<_>>();
+    let turned = Mask::from_turned_glyphs(&turned, 2).unwrap();
+
+    let ink = |mask: &Mask| mask.data.iter().sum::<f32>();
+    assert!((ink(&plain) - ink(&turned)).abs() / ink(&plain) < 0.01);
+    assert!((plain.left - turned.left).abs() <= 1 && (plain.top - turned.top).abs() <= 1);
+
+    // a quarter turn around the origin swaps the extents
+    let quarter = glyphs
+        .iter()
+        .map(|g| (g.clone(), std::f32::consts::FRAC_PI_2))
+        .collect::<Vec<_>>();
+    let quarter = Mask::from_turned_glyphs(&quarter[..1], 0).unwrap();
+    let single = Mask::from_glyphs(&glyphs[..1], 0).unwrap();
+    assert!((quarter.width as i32 - single.height as i32).abs() <= 1);
+    assert!((quarter.height as i32 - single.width as i32).abs() <= 1);
+}
+
+async fn render(app: &Router, extra: Value) -> (StatusCode, Option<DynamicImage>) {
+    let mut body = common::cover(json!({
+        "author": "A",
+        "author_position": {"Custom": {"x": 0.0, "y": 0.0, "width": 0.05, "height": 0.05}},
+        "title_font": "Garet-Heavy.ttf",
+        "title": "BOOK SERIES",
+        "title_position": {"Custom": {"x": 0.1, "y": 0.3, "width": 0.8, "height": 0.3}},
+        "image_base64": common::background_sized(384, 384)
+    }));
+    body.as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    let (status, body) = common::post(app, "/overlay", &body).await;
+    (status, image::load_from_memory(&body).ok())
+}
+
+// light pixels of the title, the author corner is left out
+fn ink(image: &DynamicImage) -> Vec<(u32, u32, u8)> {
+    image
+        .pixels()
+        .filter(|(x, y, _)| *x > 30 || *y > 30)
+        .filter(|(_, _, pixel)| pixel[0] > 40)
+        .map(|(x, y, pixel)| (x, y, pixel[0]))
+        .collect()
+}
+
+fn extent(ink: &[(u32, u32, u8)]) -> (u32, u32) {
+    let xs = ink.iter().map(|p| p.0);
+    let ys = ink.iter().map(|p| p.1);
+    (
+        xs.clone().max().unwrap() - xs.min().unwrap(),
+        ys.clone().max().unwrap() - ys.min().unwrap(),
+    )
+}
+
+// topmost ink row within a band of columns
+fn top_in(ink: &[(u32, u32, u8)], columns: std::ops::Range<u32>) -> u32 {
+    ink.iter()
+        .filter(|p| columns.contains(&p.0))
+        .map(|p| p.1)
+        .min()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn rotated_blocks_turn_around_their_center() {
+    let app = app_with_settings(Settings::default());
+    let (_, flat) = render(&app, json!({})).await;
+    let (width, height) = extent(&ink(&flat.unwrap()));
+    assert!(width > 3 * height);
+
+    let (status, upright) = render(&app, json!({"title_rotation": 90.0})).await;
+    assert_eq!(status, StatusCode::OK);
+    let upright = ink(&upright.unwrap());
+    let (turned_width, turned_height) = extent(&upright);
+    assert!(turned_height > 3 * turned_width);
+    assert!((turned_height as i32 - width as i32).abs() < 6);
+
+    // edges stay antialiased at odd angles
+    let (_, tilted) = render(&app, json!({"title_rotation": -30.0})).await;
+    let tilted = ink(&tilted.unwrap());
+    let soft = tilted.iter().filter(|p| p.2 < 200).count();
+    assert!(soft * 10 > tilted.len(), "{} of {}", soft, tilted.len());
+}
+
+#[tokio::test]
+async fn arcs_bend_lines_around_a_circle() {
+    let app = app_with_settings(Settings::default());
+    let (_, badge) = render(&app, json!({"title_arc_radius": 0.4})).await;
+    let badge = ink(&badge.unwrap());
+    let (_, seal) = render(&app, json!({"title_arc_radius": -0.4})).await;
+    let seal = ink(&seal.unwrap());
+
+    let (left, middle, right) = (0..100, 170..214, 284..384);
+    // a badge arches up in the middle, a seal curves up at the ends
+    assert!(top_in(&badge, middle.clone()) + 10 < top_in(&badge, left.clone()));
+    assert!(top_in(&badge, middle.clone()) + 10 < top_in(&badge, right.clone()));
+    assert!(top_in(&seal, middle.clone()) > top_in(&seal, left) + 10);
+    assert!(top_in(&seal, middle) > top_in(&seal, right) + 10);
+}
+
+#[tokio::test]
+async fn arc_radius_and_rotation_are_validated() {
+    let app = app_with_settings(Settings::default());
+    for extra in [
+        json!({"title_arc_radius": 0.0}),
+        json!({"author_arc_radius": 50.0}),
+        json!({"title_rotation": 720.0}),
+    ] {
+        let (status, _) = render(&app, extra.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", extra);
+    }
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{font, post};
+use image::GenericImageView;
+use litcovers_api::{
+    overlay::helpers::{fit_lines, line_height, widest_line},
+    router::app_with_settings,
+    settings::Settings,
+};
+use serde_json::json;
+
+const AUTHOR: &str = "Antoine Marie Jean-Baptiste Roger de Saint-Exupéry";
+
+#[test]
+fn fitted_lines_stay_within_the_band() {
+    let font = font("Stig.ttf");
+    for (max_width, max_height) in [(400, 40.0), (400, 200.0), (120, 300.0)] {
+        let (lines, scale) = fit_lines(AUTHOR, &font, max_width, max_height);
+        assert_eq!(lines.concat().replace(' ', ""), AUTHOR.replace(' ', ""));
+        let width = font.advance_width(&widest_line(&lines, &font), scale);
+        let height = lines.len() as f32 * line_height(&font, scale);
+        assert!(width <= max_width as f32 + 0.5, "{:?}", lines);
+        assert!(height <= max_height + 0.5, "{:?}", lines);
+        // the size is held back by one of the two limits
+        assert!(
+            width > max_width as f32 * 0.7 || height > max_height * 0.7,
+            "{:?} at {}",
+            lines,
+            scale.y
+        );
+    }
+
+    // a flat band keeps the text on one line, a tall narrow one stacks it
+    let (flat, _) = fit_lines(AUTHOR, &font, 400, 20.0);
+    assert_eq!(flat.len(), 1);
+    let (tall, _) = fit_lines(AUTHOR, &font, 120, 300.0);
+    assert!(tall.len() > 2, "{:?}", tall);
+}
+
+#[tokio::test]
+async fn top_center_text_fills_its_max_height() {
+    let app = app_with_settings(Settings::default());
+    // light rows above the middle, where the author is drawn
+    let author_rows = |max_height: f32| {
+        let body = common::cover(json!({"author": AUTHOR, "author_max_height": max_height}));
+        let app = app.clone();
+        async move {
+            let image = common::render(&app, &body).await;
+            let rows = (0..image.height() / 2)
+                .filter(|&y| (0..image.width()).any(|x| image.get_pixel(x, y)[0] > 128))
+                .collect::<Vec<_>>();
+            (rows[0], *rows.last().unwrap())
+        }
+    };
+
+    let (top, bottom) = author_rows(0.08).await;
+    assert!(bottom - top <= 31, "{}..{}", top, bottom);
+    let (top, bottom) = author_rows(0.4).await;
+    assert!(
+        bottom - top > 60 && bottom - top <= 154,
+        "{}..{}",
+        top,
+        bottom
+    );
+}
+
+#[tokio::test]
+async fn tiny_covers_and_bad_limits() {
+    let app = app_with_settings(Settings::default());
+    let tiny = common::cover(json!({"image_base64": common::background_sized(40, 40)}));
+    let image = common::render(&app, &tiny).await;
+    assert_eq!(image.dimensions(), (40, 40));
+
+    for extra in [
+        json!({"author": "a".repeat(201)}),
+        json!({"title": "a".repeat(301)}),
+        json!({"author_max_height": 0.0}),
+        json!({"title_max_height": 1.5}),
+    ] {
+        let (status, _) = post(&app, "/overlay", &common::cover(extra.clone())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", extra);
+    }
+}
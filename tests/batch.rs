@@ -0,0 +1,44 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{cover_at, post};
+use litcovers_api::{overlay::handlers::BatchItem, router::app_with_settings, settings::Settings};
+use serde_json::json;
+
+#[tokio::test]
+async fn failing_items_do_not_abort_the_batch() {
+    let app = app_with_settings(Settings::default());
+    let invalid_box = json!({"Custom": {"x": 0.5, "y": 0.5, "width": 0.8, "height": 0.2}});
+    let items = json!([
+        cover_at("not a url", json!({})),
+        cover_at("not a url", json!({"title_position": invalid_box})),
+    ]);
+
+    let (status, body) = post(&app, "/overlay/batch", &items).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let items: Vec<BatchItem> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].index, 0);
+    assert_eq!(items[0].status, 403);
+    assert_eq!(items[1].index, 1);
+    assert_eq!(items[1].status, 400);
+    assert!(items
+        .iter()
+        .all(|item| item.error.is_some() && item.data.is_none()));
+}
+
+#[tokio::test]
+async fn oversized_batch_is_rejected() {
+    let app = app_with_settings(Settings {
+        max_batch_size: 1,
+        ..Default::default()
+    });
+    let items = json!([
+        cover_at("not a url", json!({})),
+        cover_at("not a url", json!({})),
+    ]);
+
+    let (status, _) = post(&app, "/overlay/batch", &items).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
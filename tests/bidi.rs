@@ -0,0 +1,90 @@
+mod common;
+
+use axum::Router;
+use image::{DynamicImage, GenericImageView};
+use litcovers_api::{
+    overlay::{chain::FontChain, image::Direction},
+    router::app_with_settings,
+    settings::Settings,
+};
+use rusttype::{point, Scale};
+use serde_json::json;
+
+// forward draws hebrew letters
+const HEBREW: &str = "forward.ttf";
+
+fn hebrew() -> FontChain {
+    common::font(HEBREW)
+}
+
+#[test]
+fn auto_direction_follows_the_first_strong_character() {
+    assert_eq!(Direction::Auto.resolve("שלום world"), Direction::Rtl);
+    assert_eq!(Direction::Auto.resolve("2024 שלום"), Direction::Rtl);
+    assert_eq!(Direction::Auto.resolve("hello שלום"), Direction::Ltr);
+    assert_eq!(Direction::Auto.resolve("2024"), Direction::Ltr);
+    assert_eq!(Direction::Ltr.resolve("שלום"), Direction::Ltr);
+}
+
+#[test]
+fn right_to_left_runs_are_reordered() {
+    let chain = hebrew().with_direction(Direction::Rtl);
+    let font = chain.primary();
+    let id = |c: char| font.glyph(c).id();
+    let ids = |text: &str| {
+        chain
+            .layout(text, Scale::uniform(40.0), point(0.0, 40.0))
+            .iter()
+            .map(|glyph| glyph.id())
+            .collect::<Vec<_>>()
+    };
+
+    // first letter ends up rightmost
+    assert_eq!(ids("אב"), vec![id('ב'), id('א')]);
+    // numbers keep their order inside a right to left line
+    assert_eq!(
+        ids("אב 12"),
+        vec![id('1'), id('2'), id(' '), id('ב'), id('א')]
+    );
+
+    let glyphs = chain.layout("אב", Scale::uniform(40.0), point(0.0, 40.0));
+    assert!(glyphs[0].position().x < glyphs[1].position().x);
+}
+
+async fn render(app: &Router, direction: &str) -> DynamicImage {
+    let body = common::cover(json!({
+        "author": "A",
+        "title_font": HEBREW,
+        "title": "אבגדה א",
+        "title_position": "BottomLeft",
+        "title_direction": direction,
+        "line_length": 5
+    }));
+    common::render(app, &body).await
+}
+
+// mean x of the light pixels in the bottom band, where the short last line is drawn
+fn ink_center(image: &DynamicImage) -> f32 {
+    let (width, height) = image.dimensions();
+    let (mut sum, mut count) = (0.0, 0.0);
+    for y in height - 45..height - 20 {
+        for x in 0..width {
+            if image.get_pixel(x, y)[0] > 128 {
+                sum += x as f32;
+                count += 1.0;
+            }
+        }
+    }
+    assert!(count > 0.0);
+    sum / count
+}
+
+#[tokio::test]
+async fn bottom_left_is_mirrored_for_right_to_left_titles() {
+    let app = app_with_settings(Settings::default());
+    let auto = render(&app, "Auto").await;
+    let ltr = render(&app, "Ltr").await;
+    let half = auto.width() as f32 / 2.0;
+    assert!(ink_center(&auto) > half, "{}", ink_center(&auto));
+    assert!(ink_center(&ltr) < half, "{}", ink_center(&ltr));
+}
@@ -0,0 +1,176 @@
+use litcovers_api::overlay::image::{BlendMode, Image};
+
+// background ramp every mode is checked against
+const BASE: [(u8, u8, u8); 5] = [
+    (0, 0, 0),
+    (64, 128, 192),
+    (128, 128, 128),
+    (255, 255, 255),
+    (200, 50, 100),
+];
+const COLOR: (u8, u8, u8) = (100, 150, 250);
+
+fn assert_close(mode: BlendMode, actual: [u8; 4], expected: [u8; 3]) {
+    for i in 0..3 {
+        assert!(
+            (actual[i] as i16 - expected[i] as i16).abs() <= 1,
+            "{:?}: got {:?}, expected {:?}",
+            mode,
+            actual,
+            expected
+        );
+    }
+    assert_eq!(actual[3], 255);
+}
+
+#[test]
+fn blend_modes_match_reference_image() {
+    let reference: [(BlendMode, [[u8; 3]; 5]); 13] = [
+        (
+            BlendMode::None,
+            [
+                [100, 150, 250],
+                [100, 150, 250],
+                [100, 150, 250],
+                [100, 150, 250],
+                [100, 150, 250],
+            ],
+        ),
+        (
+            BlendMode::Overlay,
+            [
+                [0, 0, 0],
+                [50, 150, 252],
+                [100, 150, 250],
+                [255, 255, 255],
+                [188, 58, 196],
+            ],
+        ),
+        (
+            BlendMode::Multiply,
+            [
+                [0, 0, 0],
+                [25, 75, 188],
+                [50, 75, 125],
+                [100, 150, 250],
+                [78, 29, 98],
+            ],
+        ),
+        (
+            BlendMode::Screen,
+            [
+                [100, 150, 250],
+                [138, 202, 253],
+                [177, 202, 252],
+                [255, 255, 255],
+                [221, 170, 251],
+            ],
+        ),
+        (
+            BlendMode::SoftLight,
+            [
+                [0, 0, 0],
+                [53, 137, 220],
+                [114, 137, 178],
+                [255, 255, 255],
+                [190, 61, 157],
+            ],
+        ),
+        (
+            BlendMode::HardLight,
+            [
+                [0, 45, 245],
+                [50, 150, 252],
+                [100, 150, 250],
+                [200, 255, 255],
+                [156, 86, 248],
+            ],
+        ),
+        (
+            BlendMode::ColorDodge,
+            [
+                [0, 0, 0],
+                [105, 255, 255],
+                [210, 255, 255],
+                [255, 255, 255],
+                [255, 121, 255],
+            ],
+        ),
+        (
+            BlendMode::ColorBurn,
+            [
+                [0, 0, 0],
+                [0, 39, 190],
+                [0, 39, 125],
+                [255, 255, 255],
+                [114, 0, 96],
+            ],
+        ),
+        (
+            BlendMode::Difference,
+            [
+                [100, 150, 250],
+                [36, 22, 58],
+                [28, 22, 122],
+                [155, 105, 5],
+                [100, 100, 150],
+            ],
+        ),
+        (
+            BlendMode::Exclusion,
+            [
+                [100, 150, 250],
+                [113, 127, 65],
+                [127, 127, 127],
+                [155, 105, 5],
+                [143, 141, 153],
+            ],
+        ),
+        (
+            BlendMode::Lighten,
+            [
+                [100, 150, 250],
+                [100, 150, 250],
+                [128, 150, 250],
+                [255, 255, 255],
+                [200, 150, 250],
+            ],
+        ),
+        (
+            BlendMode::Darken,
+            [
+                [0, 0, 0],
+                [64, 128, 192],
+                [100, 128, 128],
+                [100, 150, 250],
+                [100, 50, 100],
+            ],
+        ),
+        (
+            BlendMode::Luminosity,
+            [
+                [146, 146, 146],
+                [94, 158, 222],
+                [146, 146, 146],
+                [146, 146, 146],
+                [245, 95, 145],
+            ],
+        ),
+    ];
+
+    for (mode, expected) in reference {
+        for (base, expected) in BASE.iter().zip(expected) {
+            let actual = Image::blend_mode(mode, *base, COLOR, 1.0, 1.0);
+            assert_close(mode, actual, expected);
+        }
+    }
+}
+
+#[test]
+fn coverage_and_alpha_mix_with_background() {
+    let half = Image::blend_mode(BlendMode::Multiply, (200, 200, 200), (0, 0, 0), 1.0, 0.5);
+    assert_close(BlendMode::Multiply, half, [100, 100, 100]);
+
+    let transparent = Image::blend_mode(BlendMode::Screen, (10, 20, 30), COLOR, 0.0, 1.0);
+    assert_close(BlendMode::Screen, transparent, [10, 20, 30]);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use common::{png, serve};
+use litcovers_api::{cache::InFlight, router::app_with_settings, settings::Settings};
+use serde_json::json;
+use tower::ServiceExt;
+
+async fn slow_background(State(hits): State<Arc<AtomicUsize>>) -> Vec<u8> {
+    hits.fetch_add(1, Ordering::SeqCst);
+    // keep the download open long enough for every request to miss the cache
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    png(128, 192, [90, 30, 60, 255])
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_misses_share_one_download() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let addr = serve(
+        Router::new()
+            .route("/bg.png", get(slow_background))
+            .with_state(hits.clone()),
+    );
+    let app = app_with_settings(Settings {
+        fetch_allow_private: true,
+        ..Default::default()
+    });
+    let body = common::cover_at(&format!("http://{}/bg.png", addr), json!({}));
+
+    let requests = (0..6)
+        .map(|_| {
+            let request = common::post_request("/overlay", &body);
+            tokio::spawn(app.clone().oneshot(request))
+        })
+        .collect::<Vec<_>>();
+    for request in requests {
+        assert_eq!(request.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failures_are_shared_and_not_remembered() {
+    let calls = Arc::new(InFlight::<Result<u32, String>>::default());
+    let loads = Arc::new(AtomicUsize::new(0));
+
+    let waiters = (0..4)
+        .map(|_| {
+            let calls = calls.clone();
+            let loads = loads.clone();
+            tokio::spawn(async move {
+                calls
+                    .run("key", || async move {
+                        loads.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        Err("unreachable".to_string())
+                    })
+                    .await
+            })
+        })
+        .collect::<Vec<_>>();
+    for waiter in waiters {
+        assert_eq!(waiter.await.unwrap(), Err("unreachable".to_string()));
+    }
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+    assert!(calls.is_empty());
+
+    // a finished call does not cache its result
+    assert_eq!(calls.run("key", || async { Ok(7) }).await, Ok(7));
+}
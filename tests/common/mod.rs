@@ -0,0 +1,116 @@
+// helpers shared by the integration tests, every test crate only uses some of them
+#![allow(dead_code)]
+
+use std::{
+    io::Cursor,
+    net::{SocketAddr, TcpListener},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    http::{self, Request, StatusCode},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{DynamicImage, ImageOutputFormat, Rgba, RgbaImage};
+use litcovers_api::{fonts::registry::FontRegistry, overlay::chain::FontChain};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+// runs the router on a free local port
+pub fn serve(router: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(router.into_make_service());
+    tokio::spawn(server);
+    addr
+}
+
+// a flat image of one color encoded as PNG
+pub fn png(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba(color)));
+    let mut buf = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut buf), ImageOutputFormat::Png)
+        .unwrap();
+    buf
+}
+
+// a dark 256x384 cover sent as image_base64
+pub fn background() -> String {
+    background_sized(256, 384)
+}
+
+pub fn background_sized(width: u32, height: u32) -> String {
+    STANDARD.encode(png(width, height, [30, 30, 30, 255]))
+}
+
+pub fn font(name: &str) -> FontChain {
+    let registry = FontRegistry::load("fonts", Duration::from_secs(60));
+    FontChain::single(registry.get(name).unwrap().font.clone())
+}
+
+// cover parameters drawn in white on the dark background, extra fields replace the defaults
+pub fn cover(extra: Value) -> Value {
+    merged(cover_params(json!({"image_base64": background()})), extra)
+}
+
+// the same cover with its background fetched from image_url
+pub fn cover_at(image_url: &str, extra: Value) -> Value {
+    merged(cover_params(json!({"image_url": image_url})), extra)
+}
+
+// the same cover without any background source
+pub fn cover_params(extra: Value) -> Value {
+    let body = json!({
+        "author_font": "Stig.ttf",
+        "author": "Lev Tolstoy",
+        "author_position": "TopCenter",
+        "title_font": "Stig.ttf",
+        "title": "War and Peace",
+        "title_position": "BottomCenter",
+        "blend_mode": "None",
+        "alfa": 1.0,
+        "line_length": 16
+    });
+    merged(body, extra)
+}
+
+fn merged(mut body: Value, extra: Value) -> Value {
+    body.as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    body
+}
+
+pub async fn post(app: &Router, uri: &str, body: &Value) -> (StatusCode, Vec<u8>) {
+    let response = app.clone().oneshot(post_request(uri, body)).await.unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, body.to_vec())
+}
+
+// a JSON POST, for callers that drive the router themselves
+pub fn post_request(uri: &str, body: &Value) -> Request<Body> {
+    Request::builder()
+        .method(http::Method::POST)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+// posts a cover to /overlay and decodes the rendered image
+pub async fn render(app: &Router, body: &Value) -> DynamicImage {
+    let (status, bytes) = post(app, "/overlay", body).await;
+    assert_eq!(
+        status,
+        StatusCode::OK,
+        "{}",
+        String::from_utf8_lossy(&bytes)
+    );
+    image::load_from_memory(&bytes).unwrap()
+}
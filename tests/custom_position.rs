@@ -0,0 +1,162 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use common::post;
+use image::{DynamicImage, GenericImageView};
+use litcovers_api::{router::app_with_settings, settings::Settings};
+use serde_json::{json, Value};
+
+// draws the title in a custom box on the 256x384 background, the author sits in the corner
+async fn render(app: &Router, title: &str, custom: Value) -> DynamicImage {
+    let body = common::cover(json!({
+        "author": "A",
+        "author_position": {"Custom": {"x": 0.0, "y": 0.0, "width": 0.05, "height": 0.05}},
+        "title_font": "Garet-Heavy.ttf",
+        "title": title,
+        "title_position": {"Custom": custom},
+        "line_length": 40
+    }));
+    common::render(app, &body).await
+}
+
+// light pixels of the title, the author corner is left out
+fn ink(image: &DynamicImage) -> Vec<(u32, u32)> {
+    image
+        .pixels()
+        .filter(|(x, y, _)| *x > 20 || *y > 25)
+        .filter(|(_, _, pixel)| pixel[0] > 128)
+        .map(|(x, y, _)| (x, y))
+        .collect()
+}
+
+// left, top, right and bottom of the ink
+fn ink_box(image: &DynamicImage) -> (u32, u32, u32, u32) {
+    let ink = ink(image);
+    let xs = ink.iter().map(|p| p.0);
+    let ys = ink.iter().map(|p| p.1);
+    (
+        xs.clone().min().unwrap(),
+        ys.clone().min().unwrap(),
+        xs.max().unwrap(),
+        ys.max().unwrap(),
+    )
+}
+
+// horizontal extent of every line, lines being runs of rows with ink
+fn line_widths(image: &DynamicImage) -> Vec<u32> {
+    let ink = ink(image);
+    let mut widths = Vec::new();
+    let mut line: Option<(u32, u32)> = None;
+    for y in 0..image.height() {
+        let row = ink.iter().filter(|p| p.1 == y).map(|p| p.0);
+        match (row.clone().min(), row.max(), line) {
+            (Some(left), Some(right), Some((l, r))) => line = Some((l.min(left), r.max(right))),
+            (Some(left), Some(right), None) => line = Some((left, right)),
+            (None, _, Some((l, r))) => {
+                widths.push(r - l + 1);
+                line = None;
+            }
+            _ => {}
+        }
+    }
+    widths.extend(line.map(|(l, r)| r - l + 1));
+    widths
+}
+
+#[tokio::test]
+async fn text_stays_inside_its_box() {
+    let app = app_with_settings(Settings::default());
+    // 25.6..179.2 across and 76.8..192 down
+    for fit in ["Wrap", "Width", "Stretch"] {
+        let custom = json!({"x": 0.1, "y": 0.2, "width": 0.6, "height": 0.3, "fit": fit});
+        let image = render(&app, "THE WAR\nAND THE PEACE OF OLD", custom).await;
+        let (left, top, right, bottom) = ink_box(&image);
+        assert!(left >= 24 && right <= 181, "{}: {}..{}", fit, left, right);
+        assert!(top >= 75 && bottom <= 194, "{}: {}..{}", fit, top, bottom);
+    }
+}
+
+#[tokio::test]
+async fn alignments_hug_the_box_edges() {
+    let app = app_with_settings(Settings::default());
+    // a flat box limits the line by its height, leaving room on the sides
+    let flat = |h_align: &str| json!({"x": 0.1, "y": 0.2, "width": 0.8, "height": 0.05, "h_align": h_align});
+    let (left, _, right, _) = ink_box(&render(&app, "WAR", flat("Left")).await);
+    assert!(
+        (25..=30).contains(&left) && right < 150,
+        "{}..{}",
+        left,
+        right
+    );
+    let (left, _, right, _) = ink_box(&render(&app, "WAR", flat("Right")).await);
+    assert!(
+        (225..=231).contains(&right) && left > 100,
+        "{}..{}",
+        left,
+        right
+    );
+    let (left, _, right, _) = ink_box(&render(&app, "WAR", flat("Center")).await);
+    assert!(
+        ((left + right) as i32 - 256).abs() <= 4,
+        "{}..{}",
+        left,
+        right
+    );
+
+    // a tall box limits it by its width, leaving room above and below
+    let tall = |v_align: &str| json!({"x": 0.1, "y": 0.1, "width": 0.3, "height": 0.8, "v_align": v_align});
+    let (_, top, _, bottom) = ink_box(&render(&app, "WAR", tall("Top")).await);
+    assert!(
+        (38..=50).contains(&top) && bottom < 150,
+        "{}..{}",
+        top,
+        bottom
+    );
+    let (_, top, _, bottom) = ink_box(&render(&app, "WAR", tall("Bottom")).await);
+    assert!(
+        (330..=346).contains(&bottom) && top > 250,
+        "{}..{}",
+        top,
+        bottom
+    );
+    let (_, top, _, bottom) = ink_box(&render(&app, "WAR", tall("Middle")).await);
+    assert!(top > 150 && bottom < 240, "{}..{}", top, bottom);
+}
+
+#[tokio::test]
+async fn fit_modes_size_lines_differently() {
+    let app = app_with_settings(Settings::default());
+    let boxed = |fit: &str| json!({"x": 0.1, "y": 0.1, "width": 0.8, "height": 0.8, "fit": fit});
+
+    // the longest line fills the box and the others keep its size
+    let widths = line_widths(&render(&app, "WAR\nAND PEACE", boxed("Width")).await);
+    assert_eq!(widths.len(), 2, "{:?}", widths);
+    assert!(widths[1] > 195 && widths[0] < widths[1] / 2, "{:?}", widths);
+
+    // every line fills the box on its own
+    let widths = line_widths(&render(&app, "WAR\nAND PEACE", boxed("Stretch")).await);
+    assert_eq!(widths.len(), 2, "{:?}", widths);
+    assert!(widths.iter().all(|w| *w > 195), "{:?}", widths);
+
+    // only wrap breaks a long line to grow the text in a tall box
+    let title = "THE WAR AND THE PEACE OF OLD";
+    let kept = line_widths(&render(&app, title, boxed("Width")).await);
+    let wrapped = line_widths(&render(&app, title, boxed("Wrap")).await);
+    assert_eq!(kept.len(), 1);
+    assert!(wrapped.len() > 2, "{:?}", wrapped);
+}
+
+#[tokio::test]
+async fn boxes_outside_the_image_are_rejected() {
+    let app = app_with_settings(Settings::default());
+    for custom in [
+        json!({"x": -0.1, "y": 0.2, "width": 0.5, "height": 0.3}),
+        json!({"x": 0.6, "y": 0.2, "width": 0.5, "height": 0.3}),
+        json!({"x": 0.1, "y": 0.8, "width": 0.5, "height": 0.3}),
+        json!({"x": 0.1, "y": 0.2, "width": 0.0, "height": 0.3}),
+    ] {
+        let body = common::cover(json!({"title_position": {"Custom": custom}}));
+        let (status, _) = post(&app, "/overlay", &body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", custom);
+    }
+}
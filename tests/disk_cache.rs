@@ -0,0 +1,108 @@
+mod common;
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use common::{png, serve};
+use litcovers_api::{cache::DiskCache, router::app_with_settings, settings::Settings};
+use serde_json::json;
+
+fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("litcovers-disk-cache-{}", uuid::Uuid::new_v4()))
+}
+
+#[tokio::test]
+async fn entries_survive_a_new_instance() {
+    let dir = temp_dir();
+    DiskCache::new(&dir, 1024)
+        .insert("https://example.com/a.png", b"abc")
+        .await
+        .unwrap();
+
+    let reopened = DiskCache::new(&dir, 1024);
+    assert_eq!(
+        reopened.get("https://example.com/a.png").await.unwrap(),
+        b"abc"
+    );
+    assert!(reopened.get("https://example.com/b.png").await.is_none());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn least_recently_used_files_are_evicted() {
+    let dir = temp_dir();
+    let cache = DiskCache::new(&dir, 20);
+    cache.insert("a", &[0; 10]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    cache.insert("b", &[0; 10]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    // reading "a" makes "b" the oldest file
+    assert!(cache.get("a").await.is_some());
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    cache.insert("c", &[0; 10]).await.unwrap();
+
+    assert!(cache.get("a").await.is_some());
+    assert!(cache.get("b").await.is_none());
+    assert!(cache.get("c").await.is_some());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn other_files_in_the_directory_are_left_alone() {
+    let dir = temp_dir();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("notes.txt"), [0; 100]).unwrap();
+    let cache = DiskCache::new(&dir, 20);
+    let partial = cache.path("b").with_extension("partial");
+    std::fs::write(&partial, [0; 10]).unwrap();
+
+    cache.insert("a", &[0; 10]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    cache.insert("c", &[0; 10]).await.unwrap();
+
+    // the foreign bytes do not count towards the cap
+    assert!(cache.get("a").await.is_some());
+    assert!(cache.get("c").await.is_some());
+    assert!(dir.join("notes.txt").exists());
+    assert!(partial.exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+async fn background(State(hits): State<Arc<AtomicUsize>>) -> Vec<u8> {
+    hits.fetch_add(1, Ordering::SeqCst);
+    png(128, 192, [20, 40, 60, 255])
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn restarted_app_reads_backgrounds_from_disk() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let addr = serve(
+        Router::new()
+            .route("/bg.png", get(background))
+            .with_state(hits.clone()),
+    );
+    let dir = temp_dir();
+    let settings = || Settings {
+        image_disk_cache_dir: Some(dir.clone()),
+        fetch_allow_private: true,
+        ..Default::default()
+    };
+    let body = common::cover_at(&format!("http://{}/bg.png", addr), json!({}));
+
+    // a fresh app per request has an empty memory cache, like a restarted pod
+    for _ in 0..2 {
+        let (status, _) = common::post(&app_with_settings(settings()), "/overlay", &body).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use common::{cover, post};
+use litcovers_api::{
+    fonts::registry::FontRegistry,
+    overlay::{chain::FontChain, handlers::GlyphReport},
+    router::app_with_settings,
+    settings::Settings,
+};
+use rusttype::{point, Scale};
+use serde_json::{json, Value};
+
+// kurbanistika lacks Ё and russian quotes, forward has no lowercase cyrillic
+const NO_YO: &str = "kurbanistika.ttf";
+const CAPS_CYRILLIC: &str = "forward.ttf";
+
+// a title the font lacks glyphs for, with its own fallback list
+fn title_without_yo(fallbacks: Value) -> Value {
+    json!({
+        "title_font": NO_YO,
+        "title": "Ёлка «Мир»",
+        "title_fallback_fonts": fallbacks
+    })
+}
+
+#[test]
+fn chain_uses_the_first_font_that_draws_a_character() {
+    let registry = FontRegistry::load("fonts", Duration::from_secs(60));
+    let caps = registry.get(CAPS_CYRILLIC).unwrap().font.clone();
+    let stig = registry.get("Stig.ttf").unwrap().font.clone();
+
+    let alone = FontChain::single(caps.clone());
+    assert_eq!(alone.missing("МИР мир"), vec!['м', 'и', 'р']);
+
+    let chain = FontChain::new(caps.clone(), vec![stig.clone()]);
+    assert!(chain.missing("МИР мир").is_empty());
+    let glyphs = chain.layout("Мж", Scale::uniform(40.0), point(0.0, 40.0));
+    assert_eq!(glyphs.len(), 2);
+    assert_eq!(glyphs[0].id(), caps.glyph('М').id());
+    assert_eq!(glyphs[1].id(), stig.glyph('ж').id());
+    assert!(glyphs[1].position().x > glyphs[0].position().x);
+    assert!(chain.advance_width("AЖ", Scale::uniform(40.0)) > 0.0);
+}
+
+#[tokio::test]
+async fn validation_reports_unsupported_characters() {
+    let app = app_with_settings(Settings::default());
+    let (status, body) = post(
+        &app,
+        "/overlay/validate",
+        &cover(title_without_yo(json!([]))),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let report: GlyphReport = serde_json::from_slice(&body).unwrap();
+    assert!(!report.supported);
+    assert!(report.author_missing.is_empty());
+    // bottom placements are drawn in capitals
+    assert_eq!(report.title_missing, vec!['Ё', '«', '»']);
+
+    let (_, body) = post(
+        &app,
+        "/overlay/validate",
+        &cover(title_without_yo(json!(["Stig.ttf"]))),
+    )
+    .await;
+    let report: GlyphReport = serde_json::from_slice(&body).unwrap();
+    assert!(report.supported);
+
+    let (status, _) = post(
+        &app,
+        "/overlay/validate",
+        &cover(title_without_yo(json!(["Nope.ttf"]))),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = post(
+        &app,
+        "/overlay/validate",
+        &cover(title_without_yo(json!(["../x.ttf"]))),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn validation_needs_no_background() {
+    let app = app_with_settings(Settings::default());
+    let body = common::cover_params(title_without_yo(json!(["Stig.ttf"])));
+    let (status, report) = post(&app, "/overlay/validate", &body).await;
+    assert_eq!(status, StatusCode::OK);
+    let report: GlyphReport = serde_json::from_slice(&report).unwrap();
+    assert!(report.supported);
+
+    // the rest of the cover is still checked
+    let mut body = common::cover_params(json!({}));
+    body["title_max_height"] = json!(1.5);
+    let (status, _) = post(&app, "/overlay/validate", &body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn fallback_glyphs_are_drawn() {
+    let app = app_with_settings(Settings::default());
+    let (status, without) = post(&app, "/overlay", &cover(title_without_yo(json!([])))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, with) = post(
+        &app,
+        "/overlay",
+        &cover(title_without_yo(json!(["Stig.ttf"]))),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let ink = |png: &[u8]| {
+        image::load_from_memory(png)
+            .unwrap()
+            .to_rgba8()
+            .pixels()
+            .filter(|pixel| pixel[0] > 128)
+            .count()
+    };
+    assert_ne!(ink(&without), ink(&with));
+
+    // the shared list applies to blocks without their own list
+    let mut shared = cover(title_without_yo(Value::Null));
+    shared["fallback_fonts"] = json!(["Stig.ttf"]);
+    let (_, body) = post(&app, "/overlay/validate", &shared).await;
+    let report: GlyphReport = serde_json::from_slice(&body).unwrap();
+    assert!(report.supported);
+}
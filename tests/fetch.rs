@@ -0,0 +1,212 @@
+mod common;
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    http::{self, header, StatusCode},
+    response::{IntoResponse, Redirect},
+    routing::get,
+    Router,
+};
+use common::png;
+use litcovers_api::{
+    fetch::{fetch_image, host_allowed, is_public},
+    router::app_with_settings,
+    settings::Settings,
+};
+use serde_json::json;
+
+// the served background
+fn art() -> Vec<u8> {
+    png(64, 96, [10, 20, 30, 255])
+}
+
+fn image_server() -> SocketAddr {
+    let router = Router::new()
+        .route("/bg.png", get(|| async { art() }))
+        .route("/moved", get(|| async { Redirect::temporary("/bg.png") }))
+        .route(
+            "/page.html",
+            get(|| async { ([(header::CONTENT_TYPE, "text/html")], "<html></html>") }),
+        )
+        .route(
+            "/fake.png",
+            get(|| async {
+                ([(header::CONTENT_TYPE, "image/png")], "not an image").into_response()
+            }),
+        )
+        .route(
+            "/slow.png",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                art()
+            }),
+        )
+        .route(
+            "/trickle.png",
+            get(|| async {
+                // a byte every 50ms, each well within the read timeout
+                let (mut sender, body) = Body::channel();
+                tokio::spawn(async move {
+                    for byte in art() {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        if sender.send_data(vec![byte].into()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+                http::Response::builder()
+                    .header(header::CONTENT_TYPE, "image/png")
+                    .body(body)
+                    .unwrap()
+            }),
+        );
+    common::serve(router)
+}
+
+fn local() -> Settings {
+    Settings {
+        fetch_allow_private: true,
+        ..Default::default()
+    }
+}
+
+async fn status_of(url: String, settings: &Settings) -> StatusCode {
+    fetch_image(&url, settings).await.unwrap_err().parts().0
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn private_addresses_are_refused_by_default() {
+    let addr = image_server();
+    let url = format!("http://{}/bg.png", addr);
+    assert_eq!(
+        status_of(url.clone(), &Settings::default()).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(fetch_image(&url, &local()).await.unwrap(), art());
+
+    let body = common::cover_at("http://localhost:9/metadata", json!({}));
+    let (status, _) =
+        common::post(&app_with_settings(Settings::default()), "/overlay", &body).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hosts_outside_the_allowlist_are_refused() {
+    let addr = image_server();
+    let settings = Settings {
+        fetch_allowed_hosts: vec!["replicate.delivery".to_string()],
+        ..local()
+    };
+    assert_eq!(
+        status_of(format!("http://{}/bg.png", addr), &settings).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status_of("file:///etc/passwd".to_string(), &local()).await,
+        StatusCode::FORBIDDEN
+    );
+
+    let allowed = ["replicate.delivery".to_string()];
+    assert!(host_allowed("replicate.delivery", &allowed));
+    assert!(host_allowed("PBXT.Replicate.Delivery", &allowed));
+    assert!(!host_allowed("replicate.delivery.evil.com", &allowed));
+    assert!(!host_allowed("notreplicate.delivery", &allowed));
+    assert!(host_allowed("anything.com", &[]));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn redirects_are_followed_and_rechecked() {
+    let addr = image_server();
+    let url = format!("http://{}/moved", addr);
+    assert_eq!(fetch_image(&url, &local()).await.unwrap(), art());
+
+    let settings = Settings {
+        fetch_allowed_hosts: vec!["127.0.0.1".to_string()],
+        ..local()
+    };
+    let url = format!("http://{}/moved", addr).replace("127.0.0.1", "localhost");
+    assert_eq!(status_of(url, &settings).await, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oversized_and_non_image_bodies_are_refused() {
+    let addr = image_server();
+    let small = Settings {
+        fetch_max_bytes: 64,
+        ..local()
+    };
+    assert_eq!(
+        status_of(format!("http://{}/bg.png", addr), &small).await,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+    assert_eq!(
+        status_of(format!("http://{}/page.html", addr), &local()).await,
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+    assert_eq!(
+        status_of(format!("http://{}/fake.png", addr), &local()).await,
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_servers_time_out() {
+    let addr = image_server();
+    let settings = Settings {
+        fetch_read_timeout: Duration::from_millis(200),
+        ..local()
+    };
+    assert_eq!(
+        status_of(format!("http://{}/slow.png", addr), &settings).await,
+        StatusCode::GATEWAY_TIMEOUT
+    );
+
+    let settings = Settings {
+        fetch_read_timeout: Duration::from_millis(200),
+        fetch_body_timeout: Duration::from_millis(500),
+        ..local()
+    };
+    assert_eq!(
+        status_of(format!("http://{}/trickle.png", addr), &settings).await,
+        StatusCode::GATEWAY_TIMEOUT
+    );
+}
+
+#[test]
+fn internal_ranges_are_not_public() {
+    for ip in [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "::1",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:10.0.0.1",
+        "64:ff9b::a00:1",
+        "64:ff9b::127.0.0.1",
+        "64:ff9b::169.254.169.254",
+        "2002:a00:1::1",
+        "2002:7f00:1::",
+        "2002:c0a8:101::1",
+    ] {
+        assert!(!is_public(ip.parse::<IpAddr>().unwrap()), "{}", ip);
+    }
+    for ip in [
+        "8.8.8.8",
+        "151.101.1.1",
+        "2606:4700::1111",
+        "64:ff9b::808:808",
+        "2002:808:808::1",
+    ] {
+        assert!(is_public(ip.parse::<IpAddr>().unwrap()), "{}", ip);
+    }
+}
@@ -0,0 +1,90 @@
+use std::{fs, path::PathBuf, sync::Arc, time::Duration};
+
+use litcovers_api::{fonts::registry::FontRegistry, router::app_with_settings, settings::Settings};
+
+fn temp_fonts(files: &[&str]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("litcovers-fonts-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    for file in files {
+        fs::copy(PathBuf::from("fonts").join(file), dir.join(file)).unwrap();
+    }
+    dir
+}
+
+fn ids(registry: &FontRegistry) -> Vec<String> {
+    registry.list().into_iter().map(|font| font.id).collect()
+}
+
+#[test]
+fn fonts_are_shared_and_found_by_id_or_file_name() {
+    let dir = temp_fonts(&["Stig.ttf", "Molot.ttf"]);
+    let registry = FontRegistry::load(&dir, Duration::from_secs(60));
+    assert_eq!(ids(&registry), vec!["molot-regular", "stig-regular"]);
+
+    let by_id = registry.get("stig-regular").unwrap();
+    let by_file = registry.get("Stig.ttf").unwrap();
+    assert!(Arc::ptr_eq(&by_id, &by_file));
+    assert!(Arc::ptr_eq(
+        &by_id.font,
+        &registry.get("stig-regular").unwrap().font
+    ));
+    assert!(registry.get("Missing.ttf").is_none());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn directory_changes_are_picked_up() {
+    let dir = temp_fonts(&["Stig.ttf"]);
+    let registry = FontRegistry::load(&dir, Duration::from_secs(60));
+    let stig = registry.get("stig-regular").unwrap();
+
+    fs::copy("fonts/Molot.ttf", dir.join("Molot.ttf")).unwrap();
+    fs::rename(dir.join("Stig.ttf"), dir.join("Renamed.ttf")).unwrap();
+    assert!(registry.reload().unwrap());
+    assert_eq!(ids(&registry), vec!["molot-regular", "stig-regular"]);
+
+    // the id survives the rename, the old file name alias does not
+    assert!(registry.get("Stig.ttf").is_none());
+    assert_eq!(registry.get("Renamed.ttf").unwrap().info.id, "stig-regular");
+    assert!(!Arc::ptr_eq(&stig, &registry.get("stig-regular").unwrap()));
+
+    fs::remove_file(dir.join("Molot.ttf")).unwrap();
+    assert!(registry.reload().unwrap());
+    assert_eq!(ids(&registry), vec!["stig-regular"]);
+    assert!(!registry.reload().unwrap());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn watched_directories_reload_in_the_background() {
+    let dir = temp_fonts(&["Stig.ttf"]);
+    let registry = Arc::new(FontRegistry::load(&dir, Duration::ZERO));
+    registry.watch();
+
+    // lookups never rescan themselves, the watcher does shortly after
+    fs::copy("fonts/Molot.ttf", dir.join("Molot.ttf")).unwrap();
+    assert_eq!(ids(&registry), vec!["stig-regular"]);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(ids(&registry), vec!["molot-regular", "stig-regular"]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// the watcher is started separately, so building the router needs no runtime
+#[test]
+fn router_builds_outside_a_runtime() {
+    app_with_settings(Settings::default());
+}
+
+#[test]
+fn duplicate_fonts_get_numbered_ids() {
+    let dir = temp_fonts(&["Stig.ttf"]);
+    fs::copy(dir.join("Stig.ttf"), dir.join("StigCopy.ttf")).unwrap();
+    let registry = FontRegistry::load(&dir, Duration::from_secs(60));
+    assert_eq!(ids(&registry), vec!["stig-regular", "stig-regular-2"]);
+    assert_eq!(registry.get("stig-regular").unwrap().info.file, "Stig.ttf");
+    assert_eq!(
+        registry.get("stig-regular-2").unwrap().info.file,
+        "StigCopy.ttf"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use litcovers_api::{
+    fonts::{FontInfo, Script},
+    router::app_with_settings,
+    settings::Settings,
+};
+use serde_json::json;
+use tower::ServiceExt;
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, Option<String>, Vec<u8>) {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map(|value| value.to_str().unwrap().to_string());
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, content_type, body.to_vec())
+}
+
+#[tokio::test]
+async fn catalog_lists_fonts_with_metadata() {
+    let app = app_with_settings(Settings::default());
+    let (status, _, body) = get(&app, "/fonts").await;
+    assert_eq!(status, StatusCode::OK);
+    let fonts: Vec<FontInfo> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(fonts.len(), std::fs::read_dir("fonts").unwrap().count());
+
+    let stig = fonts.iter().find(|font| font.file == "Stig.ttf").unwrap();
+    assert_eq!(stig.id, "stig-regular");
+    assert_eq!(stig.family, "Stig");
+    assert_eq!(stig.style, "Regular");
+    assert_eq!(stig.scripts, vec![Script::Latin, Script::Cyrillic]);
+
+    let garet = fonts.iter().find(|font| font.id == "garet-heavy").unwrap();
+    assert_eq!(
+        (garet.family.as_str(), garet.style.as_str()),
+        ("Garet", "Heavy")
+    );
+
+    let latin_only = fonts
+        .iter()
+        .find(|font| font.file == "kurbanistika.ttf")
+        .unwrap();
+    assert_eq!(latin_only.scripts, vec![Script::Latin]);
+}
+
+#[tokio::test]
+async fn preview_renders_the_requested_text() {
+    let app = app_with_settings(Settings::default());
+    let (status, content_type, body) =
+        get(&app, "/fonts/stig-regular/preview?text=Hi&size=32").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type.unwrap(), "image/png");
+    let short = image::load_from_memory(&body).unwrap();
+
+    let (_, _, body) = get(&app, "/fonts/Stig.ttf/preview?text=Hello%20there&size=32").await;
+    let long = image::load_from_memory(&body).unwrap();
+    assert!(long.width() > short.width());
+    assert_eq!(long.height(), short.height());
+
+    // some ink is drawn on the white strip
+    let rgba = short.to_rgba8();
+    assert!(rgba.pixels().any(|pixel| pixel[0] < 128));
+}
+
+#[tokio::test]
+async fn preview_rejects_unknown_fonts_and_bad_params() {
+    let app = app_with_settings(Settings::default());
+    let (status, _, _) = get(&app, "/fonts/Missing.ttf/preview").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, _) = get(&app, "/fonts/..%2FCargo.toml/preview").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = get(&app, "/fonts/Stig.ttf/preview?size=1000").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+async fn overlay(app: &Router, author_font: &str) -> (StatusCode, String) {
+    let body = common::cover_at(
+        "https://replicate.delivery/out-0.png",
+        json!({ "author_font": author_font }),
+    );
+    let (status, body) = common::post(app, "/overlay", &body).await;
+    (status, String::from_utf8(body).unwrap())
+}
+
+#[tokio::test]
+async fn unknown_fonts_are_not_found_with_suggestions() {
+    let app = app_with_settings(Settings::default());
+    let (status, body) = overlay(&app, "Stgi.ttf").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body.contains("\"Stgi.ttf\""), "{}", body);
+    assert!(body.contains("[Stig.ttf, "), "{}", body);
+
+    let (status, body) = overlay(&app, "garet-havy").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body.contains("[garet-heavy, "), "{}", body);
+}
+
+#[tokio::test]
+async fn font_names_cannot_be_paths() {
+    let app = app_with_settings(Settings::default());
+    for name in [
+        "../Cargo.toml",
+        "/etc/passwd",
+        "fonts/Stig.ttf",
+        "..",
+        ".env",
+        "",
+    ] {
+        let (status, body) = overlay(&app, name).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", name);
+        assert!(body.contains("author_font"), "{}", body);
+    }
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, Request, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use common::{png, serve};
+use litcovers_api::{router::app_with_settings, settings::Settings};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+#[derive(Clone)]
+struct Mock {
+    addr: Arc<std::sync::OnceLock<SocketAddr>>,
+    creates: Arc<AtomicUsize>,
+    polls: Arc<AtomicUsize>,
+    fail: bool,
+}
+
+async fn create(
+    State(mock): State<Mock>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> (StatusCode, Json<Value>) {
+    mock.creates.fetch_add(1, Ordering::SeqCst);
+    assert_eq!(headers[header::AUTHORIZATION], "Token test-token");
+    assert_eq!(body["version"], "v1");
+    assert_eq!(body["input"]["prompt"], "a castle in the clouds");
+    assert_eq!(body["input"]["width"], 256);
+    (
+        StatusCode::CREATED,
+        Json(json!({"id": "p1", "status": "starting"})),
+    )
+}
+
+async fn poll(State(mock): State<Mock>) -> Json<Value> {
+    if mock.polls.fetch_add(1, Ordering::SeqCst) == 0 {
+        return Json(json!({"id": "p1", "status": "processing"}));
+    }
+    if mock.fail {
+        return Json(json!({"id": "p1", "status": "failed", "error": "NSFW content detected"}));
+    }
+    let url = format!("http://{}/out-0.png", mock.addr.get().unwrap());
+    Json(json!({"id": "p1", "status": "succeeded", "output": [url]}))
+}
+
+async fn output() -> Vec<u8> {
+    png(256, 384, [30, 30, 60, 255])
+}
+
+fn mock_replicate(fail: bool) -> (SocketAddr, Mock) {
+    let mock = Mock {
+        addr: Arc::default(),
+        creates: Arc::default(),
+        polls: Arc::default(),
+        fail,
+    };
+    let addr = serve(
+        Router::new()
+            .route("/v1/predictions", post(create))
+            .route("/v1/predictions/p1", get(poll))
+            .route("/out-0.png", get(output))
+            .with_state(mock.clone()),
+    );
+    mock.addr.set(addr).unwrap();
+    (addr, mock)
+}
+
+fn generate_request(author_font: &str) -> Request<Body> {
+    generate_request_with(author_font, json!({}))
+}
+
+// extra cover fields are merged over the defaults
+fn generate_request_with(author_font: &str, extra: Value) -> Request<Body> {
+    let mut cover = common::cover_params(json!({
+        "author_font": author_font,
+        "blend_mode": "Overlay"
+    }));
+    cover
+        .as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    let body = json!({
+        "prompt": "a castle in the clouds",
+        "version": "v1",
+        "input": {"width": 256},
+        "cover": cover
+    });
+    common::post_request("/generate", &body)
+}
+
+fn settings(addr: SocketAddr) -> Settings {
+    Settings {
+        replicate_token: "test-token".to_string(),
+        replicate_base_url: format!("http://{}", addr),
+        replicate_poll_interval: Duration::from_millis(10),
+        fetch_allow_private: true,
+        ..Default::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn generate_overlays_prediction_output() {
+    let (addr, mock) = mock_replicate(false);
+    let app = app_with_settings(settings(addr));
+
+    let response = app.oneshot(generate_request("Stig.ttf")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+    assert_eq!(mock.polls.load(Ordering::SeqCst), 2);
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let cover = image::load_from_memory(&body).unwrap();
+    assert_eq!((cover.width(), cover.height()), (256, 384));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_prediction_is_reported() {
+    let (addr, _mock) = mock_replicate(true);
+    let app = app_with_settings(settings(addr));
+
+    let response = app.oneshot(generate_request("Stig.ttf")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unrenderable_cover_creates_no_prediction() {
+    let (addr, mock) = mock_replicate(false);
+    let app = app_with_settings(settings(addr));
+
+    let response = app.oneshot(generate_request("Stgi.ttf")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(mock.creates.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn covers_with_their_own_background_create_no_prediction() {
+    let (addr, mock) = mock_replicate(false);
+    let app = app_with_settings(settings(addr));
+
+    for extra in [
+        json!({"image_base64": common::background()}),
+        json!({"image_url": "https://example.com/bg.png"}),
+    ] {
+        let request = generate_request_with("Stig.ttf", extra.clone());
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", extra);
+    }
+    assert_eq!(mock.creates.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hanging_replicate_calls_time_out() {
+    let addr = serve(Router::new().route(
+        "/v1/predictions",
+        post(|| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            StatusCode::CREATED
+        }),
+    ));
+    let app = app_with_settings(Settings {
+        replicate_request_timeout: Duration::from_millis(200),
+        ..settings(addr)
+    });
+
+    let response = app.oneshot(generate_request("Stig.ttf")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+}
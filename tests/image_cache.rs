@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use image::{DynamicImage, RgbaImage};
+use litcovers_api::cache::ImageCache;
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+#[test]
+fn least_recently_used_entry_is_evicted_first() {
+    let cache = ImageCache::new(30, MINUTE, false);
+    cache.insert("a", vec![0; 10], None);
+    cache.insert("b", vec![0; 10], None);
+    cache.insert("c", vec![0; 10], None);
+
+    // reading "a" makes "b" the oldest
+    assert!(cache.get("a").is_some());
+    cache.insert("d", vec![0; 10], None);
+
+    assert!(cache.get("b").is_none());
+    assert!(cache.get("a").is_some());
+    assert!(cache.get("c").is_some());
+    assert!(cache.get("d").is_some());
+
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 3);
+    assert_eq!(stats.bytes, 30);
+    assert_eq!(stats.evictions, 1);
+    assert_eq!(stats.hits, 4);
+    assert_eq!(stats.misses, 1);
+}
+
+#[test]
+fn entries_expire_after_ttl() {
+    let cache = ImageCache::new(100, Duration::from_millis(20), false);
+    cache.insert("a", vec![0; 10], None);
+    assert!(cache.get("a").is_some());
+
+    std::thread::sleep(Duration::from_millis(30));
+    assert!(cache.get("a").is_none());
+    assert_eq!(cache.stats().entries, 0);
+}
+
+#[test]
+fn oversized_entries_are_not_cached() {
+    let cache = ImageCache::new(10, MINUTE, false);
+    cache.insert("a", vec![0; 11], None);
+    assert!(cache.get("a").is_none());
+}
+
+#[test]
+fn decoded_images_count_towards_the_size_cap() {
+    let decoded = DynamicImage::ImageRgba8(RgbaImage::new(2, 2));
+
+    let cache = ImageCache::new(100, MINUTE, true);
+    cache.insert("a", vec![0; 10], Some(decoded.clone()));
+    assert!(cache.get("a").unwrap().decoded.is_some());
+    assert_eq!(cache.stats().bytes, 10 + 16);
+
+    let bytes_only = ImageCache::new(100, MINUTE, false);
+    bytes_only.insert("a", vec![0; 10], Some(decoded));
+    assert!(bytes_only.get("a").unwrap().decoded.is_none());
+    assert_eq!(bytes_only.stats().bytes, 10);
+}
@@ -0,0 +1,267 @@
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderMap, Request, StatusCode},
+    routing::{get, post},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::{cover_at, serve};
+use litcovers_api::{
+    jobs::{sign, JobStatus, JobView, SIGNATURE_HEADER},
+    router::{app_with_settings, app_with_state, state_with_settings},
+    settings::Settings,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+type Received = Arc<Mutex<Vec<(Option<String>, Bytes)>>>;
+
+async fn webhook(State(received): State<Received>, headers: HeaderMap, body: Bytes) {
+    let signature = headers
+        .get(SIGNATURE_HEADER)
+        .map(|value| value.to_str().unwrap().to_string());
+    received.lock().unwrap().push((signature, body));
+}
+
+// posts a job and reads back the created view
+async fn create(app: &Router, body: Value) -> JobView {
+    let (status, body) = common::post(app, "/jobs", &body).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn poll_until_finished(app: &Router, id: &str) -> JobView {
+    for _ in 0..100 {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/jobs/{}", id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let view: JobView = serde_json::from_slice(&body).unwrap();
+        if matches!(view.status, JobStatus::Succeeded | JobStatus::Failed) {
+            return view;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("job {} did not finish", id);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn job_finishes_and_calls_signed_webhook() {
+    let received: Received = Arc::default();
+    let hook = serve(
+        Router::new()
+            .route("/hook", post(webhook))
+            .with_state(received.clone()),
+    );
+    let app = app_with_settings(Settings {
+        webhook_secret: "secret".to_string(),
+        fetch_allow_private: true,
+        ..Default::default()
+    });
+
+    let body = json!({
+        "covers": [cover_at("not a url", json!({}))],
+        "webhook_url": format!("http://{}/hook", hook),
+    });
+    let created = create(&app, body).await;
+
+    let view = poll_until_finished(&app, &created.id).await;
+    assert_eq!(view.status, JobStatus::Succeeded);
+    assert_eq!(view.progress, 1.0);
+    let items = view.result.unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].status, 403);
+
+    for _ in 0..100 {
+        if !received.lock().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let (signature, body) = received.lock().unwrap().pop().expect("webhook was called");
+    assert_eq!(signature.unwrap(), sign("secret", &body));
+    let delivered: JobView = serde_json::from_slice(&body).unwrap();
+    assert_eq!(delivered.id, created.id);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rendered_covers_are_stored_and_delivered() {
+    let received: Received = Arc::default();
+    let hook = serve(
+        Router::new()
+            .route("/hook", post(webhook))
+            .with_state(received.clone()),
+    );
+    let app = app_with_settings(Settings {
+        webhook_secret: "secret".to_string(),
+        fetch_allow_private: true,
+        ..Default::default()
+    });
+
+    let body = json!({
+        "covers": [common::cover(json!({}))],
+        "webhook_url": format!("http://{}/hook", hook),
+    });
+    let created = create(&app, body).await;
+
+    let view = poll_until_finished(&app, &created.id).await;
+    assert_eq!(view.status, JobStatus::Succeeded);
+    let items = view.result.unwrap();
+    assert_eq!(items[0].status, 200);
+    assert_eq!(items[0].content_type.as_deref(), Some("image/png"));
+    let data = items[0].data.clone().unwrap();
+    let image = image::load_from_memory(&STANDARD.decode(&data).unwrap()).unwrap();
+    assert_eq!((image.width(), image.height()), (256, 384));
+    // the dark background has white text on it
+    assert!(image.to_rgba8().pixels().any(|pixel| pixel[0] > 200));
+
+    for _ in 0..100 {
+        if !received.lock().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let (signature, body) = received.lock().unwrap().pop().expect("webhook was called");
+    assert_eq!(signature.unwrap(), sign("secret", &body));
+    let delivered: JobView = serde_json::from_slice(&body).unwrap();
+    assert_eq!(delivered.id, created.id);
+    assert_eq!(delivered.status, JobStatus::Succeeded);
+    let delivered = delivered.result.unwrap();
+    assert_eq!(delivered[0].status, 200);
+    assert_eq!(delivered[0].data.as_deref(), Some(data.as_str()));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn webhooks_to_private_addresses_are_not_sent() {
+    let received: Received = Arc::default();
+    let hook = serve(
+        Router::new()
+            .route("/hook", post(webhook))
+            .with_state(received.clone()),
+    );
+    let app = app_with_settings(Settings::default());
+
+    let body = json!({
+        "covers": [cover_at("not a url", json!({}))],
+        "webhook_url": format!("http://{}/hook", hook),
+    });
+    let created = create(&app, body).await;
+
+    let view = poll_until_finished(&app, &created.id).await;
+    assert_eq!(view.status, JobStatus::Succeeded);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(received.lock().unwrap().is_empty());
+}
+
+fn slow_server() -> std::net::SocketAddr {
+    serve(Router::new().route(
+        "/slow.png",
+        get(|| async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            StatusCode::OK
+        }),
+    ))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_job_times_out() {
+    let slow = slow_server();
+    let app = app_with_settings(Settings {
+        job_timeout: Duration::from_millis(200),
+        fetch_allow_private: true,
+        ..Default::default()
+    });
+
+    let body = json!({ "covers": [cover_at(&format!("http://{}/slow.png", slow), json!({}))] });
+    let created = create(&app, body).await;
+
+    let view = poll_until_finished(&app, &created.id).await;
+    assert_eq!(view.status, JobStatus::Failed);
+    assert!(view.error.unwrap().contains("Timeout"));
+
+    // the aborted cover never reports back once the download would have finished
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let view = poll_until_finished(&app, &created.id).await;
+    assert_eq!(view.status, JobStatus::Failed);
+    assert_eq!(view.progress, 0.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn job_timing_out_while_drawing_keeps_its_worker_until_done() {
+    let state = state_with_settings(Settings {
+        job_timeout: Duration::from_millis(100),
+        batch_concurrency: 1,
+        ..Default::default()
+    });
+    let app = app_with_state(state.clone());
+
+    // a large background with a wide blurred shadow takes far longer to draw than the timeout
+    let cover = common::cover(json!({
+        "image_base64": common::background_sized(1200, 1800),
+        "title_style": {"shadow": {"color": "#000000", "blur": 32, "offset": [8, 8]}},
+        "author_style": {"shadow": {"color": "#000000", "blur": 32, "offset": [8, 8]}}
+    }));
+    let created = create(&app, json!({ "covers": [cover] })).await;
+
+    let view = poll_until_finished(&app, &created.id).await;
+    assert_eq!(view.status, JobStatus::Failed);
+    assert!(view.error.unwrap().contains("Timeout"));
+    // the drawing goes on after the timeout and the next cover has to wait for it
+    assert_eq!(state.render_workers.available_permits(), 0);
+
+    for _ in 0..600 {
+        if state.render_workers.available_permits() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(state.render_workers.available_permits(), 1);
+    let view = poll_until_finished(&app, &created.id).await;
+    assert_eq!(view.progress, 0.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn full_queue_refuses_new_jobs() {
+    let slow = slow_server();
+    let app = app_with_settings(Settings {
+        max_queued_jobs: 1,
+        fetch_allow_private: true,
+        ..Default::default()
+    });
+
+    let body = json!({ "covers": [cover_at(&format!("http://{}/slow.png", slow), json!({}))] });
+    create(&app, body.clone()).await;
+    let (status, _) = common::post(&app, "/jobs", &body).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn unknown_job_is_not_found() {
+    let app = app_with_settings(Settings::default());
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/jobs/missing")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
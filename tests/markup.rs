@@ -0,0 +1,111 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{font, post};
+use litcovers_api::{
+    overlay::{handlers::GlyphReport, wrap::wrap_balanced},
+    router::app_with_settings,
+    settings::Settings,
+};
+use rusttype::{point, Scale};
+use serde_json::json;
+
+#[test]
+fn line_breaks_in_the_text_are_kept() {
+    let font = font("Garet-Heavy.ttf");
+    let lines = wrap_balanced("THE\nLAST\nKINGDOM", &font, 16, true);
+    assert_eq!(lines, vec!["THE", "LAST", "KINGDOM"]);
+
+    // paragraphs are still wrapped on their own, blank lines are dropped
+    let lines = wrap_balanced("A Song of Ice and Fire\n\nBook One", &font, 10, false);
+    assert_eq!(lines.last().unwrap(), "Book One");
+    assert!(lines.len() > 2, "{:?}", lines);
+}
+
+#[test]
+fn marked_spans_are_set_in_the_emphasis_chain() {
+    let garet = font("Garet-Heavy.ttf");
+    let stig = font("Stig.ttf");
+    let scale = Scale::uniform(40.0);
+
+    // without an emphasis the asterisks are plain text
+    assert!(garet.advance_width("*AB*", scale) > garet.advance_width("AB", scale));
+
+    let chain = garet.clone().with_emphasis(stig.clone(), 1.5);
+    let expected = garet.advance_width("X ", scale)
+        + stig.advance_width("AB", Scale::uniform(60.0))
+        + garet.advance_width(" Y", scale);
+    let width = chain.advance_width("X *AB* Y", scale);
+    assert!((width - expected).abs() < 1.0, "{} {}", width, expected);
+
+    let glyphs = chain.layout_spans("X *AB* Y", scale, point(0.0, 40.0));
+    let marked = glyphs
+        .iter()
+        .map(|(_, emphasized)| *emphasized)
+        .collect::<Vec<_>>();
+    assert_eq!(marked, vec![false, false, true, true, false, false]);
+    assert_eq!(glyphs[2].0.scale(), Scale::uniform(60.0));
+
+    // a span left open runs to the end of the line
+    assert!(chain.missing("*Ё").is_empty());
+}
+
+#[test]
+fn wrapped_spans_are_closed_on_every_line() {
+    let garet = font("Garet-Heavy.ttf");
+    let chain = garet.clone().with_emphasis(garet, 1.2);
+    let lines = wrap_balanced("*THE LAST* KINGDOM", &chain, 4, false);
+    assert_eq!(lines, vec!["*THE*", "*LAST*", "KINGDOM"]);
+}
+
+#[tokio::test]
+async fn bottom_titles_may_keep_their_case() {
+    let app = app_with_settings(Settings::default());
+    // forward has no lowercase cyrillic
+    let mut body = common::cover(json!({
+        "title_font": "forward.ttf",
+        "title": "мир",
+        "title_fallback_fonts": []
+    }));
+    let (_, report) = post(&app, "/overlay/validate", &body).await;
+    let report: GlyphReport = serde_json::from_slice(&report).unwrap();
+    assert!(report.supported);
+
+    body["title_uppercase"] = json!(false);
+    let (_, report) = post(&app, "/overlay/validate", &body).await;
+    let report: GlyphReport = serde_json::from_slice(&report).unwrap();
+    assert_eq!(report.title_missing, vec!['м', 'и', 'р']);
+}
+
+#[tokio::test]
+async fn emphasis_is_drawn_in_its_color() {
+    let app = app_with_settings(Settings::default());
+    let red = |png: &[u8]| {
+        image::load_from_memory(png)
+            .unwrap()
+            .to_rgba8()
+            .pixels()
+            .filter(|pixel| pixel[0] > 200 && pixel[1] < 60 && pixel[2] < 60)
+            .count()
+    };
+
+    let mut body = common::cover(json!({
+        "title_font": "forward.ttf",
+        "title": "THE *LAST* KINGDOM",
+        "title_fallback_fonts": []
+    }));
+    let (status, plain) = post(&app, "/overlay", &body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(red(&plain), 0);
+
+    body["title_style"] = json!({
+        "emphasis": { "font": "Stig.ttf", "size": 1.2, "color": "#ff0000" }
+    });
+    let (status, marked) = post(&app, "/overlay", &body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(red(&marked) > 50);
+
+    body["title_style"]["emphasis"]["size"] = json!(10.0);
+    let (status, _) = post(&app, "/overlay", &body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,123 @@
+use std::io::{Cursor, Read};
+
+use axum::{http::header, response::IntoResponse};
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use litcovers_api::overlay::output::{
+    encode, encode_with_options, package_renditions, OutputFormat, OutputOptions, Packaging,
+    Renditions,
+};
+
+fn sample_image() -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 96, |x, y| {
+        Rgba([(x * 4) as u8, (y * 2) as u8, ((x + y) * 3) as u8, 255])
+    }))
+}
+
+#[test]
+fn accept_header_picks_best_supported_format() {
+    assert_eq!(
+        OutputFormat::from_accept("image/webp;q=0.9, image/avif;q=0.5"),
+        Some(OutputFormat::Webp)
+    );
+    assert_eq!(
+        OutputFormat::from_accept("text/html, image/jpeg"),
+        Some(OutputFormat::Jpeg)
+    );
+    assert_eq!(OutputFormat::from_accept("*/*"), Some(OutputFormat::Png));
+    assert_eq!(OutputFormat::from_accept("text/html"), None);
+
+    let explicit = OutputOptions {
+        format: Some(OutputFormat::Jpeg),
+        ..Default::default()
+    };
+    assert_eq!(
+        explicit.resolve_format(Some("image/webp")),
+        OutputFormat::Jpeg
+    );
+}
+
+#[test]
+fn negotiated_formats_vary_on_accept() {
+    let image = sample_image();
+    let negotiated = encode_with_options(&image, &OutputOptions::default(), Some("image/webp"))
+        .unwrap()
+        .into_response();
+    assert_eq!(negotiated.headers()[header::CONTENT_TYPE], "image/webp");
+    assert_eq!(negotiated.headers()[header::VARY], "Accept");
+
+    let explicit = OutputOptions {
+        format: Some(OutputFormat::Jpeg),
+        ..Default::default()
+    };
+    let explicit = encode_with_options(&image, &explicit, Some("image/webp"))
+        .unwrap()
+        .into_response();
+    assert!(!explicit.headers().contains_key(header::VARY));
+}
+
+#[test]
+fn every_format_encodes() {
+    let image = sample_image();
+    for (format, expected) in [
+        (OutputFormat::Png, ImageFormat::Png),
+        (OutputFormat::Jpeg, ImageFormat::Jpeg),
+        (OutputFormat::Webp, ImageFormat::WebP),
+    ] {
+        let bytes = encode(&image, format, 80).unwrap();
+        assert_eq!(image::guess_format(&bytes).unwrap(), expected);
+    }
+
+    let avif = encode(&image, OutputFormat::Avif, 80).unwrap();
+    assert_eq!(&avif[4..12], b"ftypavif");
+}
+
+#[test]
+fn max_bytes_lowers_quality() {
+    let image = sample_image();
+    let full = encode(&image, OutputFormat::Jpeg, 100).unwrap();
+    let options = OutputOptions {
+        format: Some(OutputFormat::Jpeg),
+        quality: Some(100),
+        max_bytes: Some(full.len() / 2),
+    };
+    let encoded = encode_with_options(&image, &options, None).unwrap();
+    assert!(encoded.bytes.len() < full.len());
+}
+
+#[test]
+fn renditions_are_resized_and_packaged() {
+    let image = sample_image();
+    let renditions: Renditions = serde_json::from_str(
+        r#"{"sizes": [{"name": "web", "width": 32, "height": 48}, {"width": 10, "height": 30}]}"#,
+    )
+    .unwrap();
+
+    let zipped = package_renditions(&image, &renditions, &OutputOptions::default(), None).unwrap();
+    assert_eq!(zipped.content_type, "application/zip");
+    let mut archive = zip::ZipArchive::new(Cursor::new(zipped.bytes)).unwrap();
+    let mut names = archive
+        .file_names()
+        .map(|name| name.unwrap().to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, vec!["10x30.png", "web.png"]);
+
+    let mut file = archive.by_name("10x30.png").unwrap();
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+    let thumb = image::load_from_memory(&bytes).unwrap();
+    assert_eq!((thumb.width(), thumb.height()), (10, 30));
+
+    let multipart = Renditions {
+        packaging: Packaging::Multipart,
+        ..renditions
+    };
+    let packaged = package_renditions(&image, &multipart, &OutputOptions::default(), None).unwrap();
+    let boundary = packaged
+        .content_type
+        .strip_prefix("multipart/mixed; boundary=")
+        .unwrap();
+    let body = String::from_utf8_lossy(&packaged.bytes);
+    assert_eq!(body.matches(&format!("--{}\r\n", boundary)).count(), 2);
+    assert!(body.ends_with(&format!("--{}--\r\n", boundary)));
+}
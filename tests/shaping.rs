@@ -0,0 +1,66 @@
+use std::{path::Path, time::Duration};
+
+use litcovers_api::{
+    fonts::registry::FontRegistry,
+    overlay::{
+        chain::FontChain,
+        helpers::{calc_font_size, widest_line},
+    },
+};
+use rusttype::{point, Scale};
+
+// garet heavy ships kerning pairs and an fi ligature
+fn chain(primary: &str, fallbacks: &[&str]) -> FontChain {
+    let registry = FontRegistry::load(Path::new("fonts"), Duration::from_secs(60));
+    let font = |name: &str| registry.get(name).unwrap().font.clone();
+    FontChain::new(
+        font(primary),
+        fallbacks.iter().map(|name| font(name)).collect(),
+    )
+}
+
+#[test]
+fn kerning_and_ligatures_are_applied() {
+    let garet = chain("Garet-Heavy.ttf", &[]);
+    let scale = Scale::uniform(100.0);
+    let pair = garet.advance_width("AV", scale);
+    let apart = garet.advance_width("A", scale) + garet.advance_width("V", scale);
+    assert!(pair < apart, "{} >= {}", pair, apart);
+
+    let (glyphs, _) = garet.shape("fi", scale);
+    assert_eq!(glyphs.len(), 1);
+}
+
+#[test]
+fn measured_width_matches_laid_out_glyphs() {
+    let mixed = chain("kurbanistika.ttf", &["Stig.ttf"]);
+    let scale = Scale::uniform(64.0);
+    for text in ["AVATAR WAVE", "Ёлка «Мир»", "office fit"] {
+        let start = point(10.0, 80.0);
+        let glyphs = mixed.layout(text, scale, start);
+        let last = glyphs.last().unwrap();
+        let end = last.position().x + last.unpositioned().h_metrics().advance_width;
+        let width = mixed.advance_width(text, scale);
+        assert!(
+            (end - start.x - width).abs() < 0.01,
+            "{}: {} vs {}",
+            text,
+            end,
+            width
+        );
+    }
+}
+
+#[test]
+fn stretched_lines_fill_the_width_exactly() {
+    let garet = chain("Garet-Heavy.ttf", &[]);
+    let lines = vec!["WAVY".to_string(), "iiiiiii".to_string()];
+    let widest = widest_line(&lines, &garet);
+    assert_eq!(widest, "WAVY");
+
+    let scale = calc_font_size(400, &widest, &garet);
+    let glyphs = garet.layout(&widest, scale, point(0.0, 0.0));
+    let last = glyphs.last().unwrap();
+    let end = last.position().x + last.unpositioned().h_metrics().advance_width;
+    assert!((end - 400.0).abs() < 0.5, "{}", end);
+}
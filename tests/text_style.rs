@@ -0,0 +1,117 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::post;
+use image::{DynamicImage, GenericImageView};
+use litcovers_api::{
+    overlay::{
+        mask::Mask,
+        style::{Color, Fill, TextStyle},
+    },
+    router::app_with_settings,
+    settings::Settings,
+};
+use serde_json::json;
+
+#[test]
+fn colors_parse_from_hex_and_arrays() {
+    let colors: Vec<Color> =
+        serde_json::from_str(r##"["#fff", "#ff8000", "10203040", [1, 2, 3], [1, 2, 3, 4]]"##)
+            .unwrap();
+    assert_eq!(
+        colors,
+        vec![
+            Color::rgba(255, 255, 255, 255),
+            Color::rgba(255, 128, 0, 255),
+            Color::rgba(16, 32, 48, 64),
+            Color::rgba(1, 2, 3, 255),
+            Color::rgba(1, 2, 3, 4),
+        ]
+    );
+    assert!(serde_json::from_str::<Color>(r##""#12345""##).is_err());
+}
+
+#[test]
+fn style_defaults_to_plain_white() {
+    let style: TextStyle = serde_json::from_str("{}").unwrap();
+    assert_eq!(style.color, Color::WHITE);
+    assert!(style.stroke.is_none());
+    assert!(style.shadow.is_none());
+}
+
+#[test]
+fn gradients_interpolate_between_stops() {
+    let linear: Fill = serde_json::from_str(
+        r##"{"Linear": {"angle": 0, "stops": [{"offset": 0, "color": "#000000"}, {"offset": 1, "color": "#ffffff"}]}}"##,
+    )
+    .unwrap();
+    let paint = linear.painter(100, 10);
+    assert_eq!(paint(0.0, 5.0), Color::BLACK);
+    assert_eq!(paint(50.0, 5.0), Color::rgba(128, 128, 128, 255));
+    assert_eq!(paint(100.0, 5.0), Color::WHITE);
+
+    let radial: Fill = serde_json::from_str(
+        r##"{"Radial": {"stops": [{"offset": 0, "color": "#ff0000"}, {"offset": 1, "color": "#0000ff"}]}}"##,
+    )
+    .unwrap();
+    let paint = radial.painter(100, 100);
+    assert_eq!(paint(50.0, 50.0), Color::rgba(255, 0, 0, 255));
+    assert_eq!(paint(0.0, 50.0), Color::rgba(0, 0, 255, 255));
+}
+
+#[test]
+fn dilation_grows_a_disk_with_a_soft_edge() {
+    let mut mask = Mask::new(0, 0, 21, 21);
+    mask.data[10 * 21 + 10] = 1.0;
+    let grown = mask.dilate(4.0);
+    assert_eq!(grown.get(10, 10), 1.0);
+    assert_eq!(grown.get(14, 10), 0.5);
+    assert_eq!(grown.get(13, 10), 1.0);
+    assert_eq!(grown.get(15, 10), 0.0);
+    // diagonals follow the circle
+    assert_eq!(grown.get(12, 12), 1.0);
+    assert!((grown.get(13, 13) - (4.5 - 18f32.sqrt())).abs() < 1e-4);
+    assert_eq!(grown.get(14, 14), 0.0);
+}
+
+fn count(image: &DynamicImage, color: [u8; 3]) -> usize {
+    image
+        .pixels()
+        .filter(|(_, _, pixel)| pixel.0[..3] == color)
+        .count()
+}
+
+#[tokio::test]
+async fn strokes_and_shadows_are_drawn() {
+    let app = app_with_settings(Settings::default());
+    let plain = common::render(&app, &common::cover(json!({}))).await;
+    assert_eq!(count(&plain, [255, 0, 0]), 0);
+    assert_eq!(count(&plain, [0, 255, 0]), 0);
+
+    let styled = common::cover(json!({
+        "title_style": {
+            "stroke": {"color": "#ff0000", "width": 3},
+            "shadow": {"color": "#00ff00", "offset": [8, 8]}
+        }
+    }));
+    let image = common::render(&app, &styled).await;
+    assert!(count(&image, [255, 0, 0]) > 200);
+    assert!(count(&image, [0, 255, 0]) > 200);
+    // the fill stays on top of the outline
+    assert!(count(&image, [255, 255, 255]) > 200);
+}
+
+#[tokio::test]
+async fn oversized_effects_are_rejected() {
+    let app = app_with_settings(Settings::default());
+    for style in [
+        json!({"stroke": {"color": "#ff0000", "width": 100000}}),
+        json!({"stroke": {"color": "#ff0000", "width": -1}}),
+        json!({"shadow": {"blur": 1e6}}),
+        json!({"shadow": {"offset": [0, -500]}}),
+    ] {
+        let body = common::cover(json!({ "title_style": style.clone() }));
+        let (status, _) = post(&app, "/overlay", &body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", style);
+    }
+}
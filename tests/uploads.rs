@@ -0,0 +1,147 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{self, Request, StatusCode},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::{cover_params, png, post};
+use litcovers_api::{cache::CacheStats, router::app_with_settings, settings::Settings};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+const BOUNDARY: &str = "cover-upload-boundary";
+
+// the uploaded background
+fn art() -> Vec<u8> {
+    png(128, 192, [200, 180, 40, 255])
+}
+
+fn multipart(params: Option<&Value>, image: Option<&[u8]>) -> Request<Body> {
+    let mut body = Vec::new();
+    if let Some(params) = params {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"params\"\r\nContent-Type: application/json\r\n\r\n{}\r\n",
+                BOUNDARY, params
+            )
+            .as_bytes(),
+        );
+    }
+    if let Some(image) = image {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"image\"; filename=\"art.png\"\r\nContent-Type: image/png\r\n\r\n",
+                BOUNDARY
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(image);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+
+    Request::builder()
+        .method(http::Method::POST)
+        .uri("/overlay")
+        .header(
+            http::header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn cache_stats(app: &Router) -> CacheStats {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/state")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn multipart_upload_renders_without_caching() {
+    let app = app_with_settings(Settings::default());
+    let response = app
+        .clone()
+        .oneshot(multipart(Some(&cover_params(json!({}))), Some(&art())))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[http::header::CONTENT_TYPE], "image/png");
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let rendered = image::load_from_memory(&body).unwrap();
+    assert_eq!((rendered.width(), rendered.height()), (128, 192));
+
+    let stats = cache_stats(&app).await;
+    assert_eq!(stats.entries, 0);
+    assert_eq!(stats.misses, 0);
+}
+
+#[tokio::test]
+async fn base64_field_renders() {
+    let app = app_with_settings(Settings::default());
+    let mut body = cover_params(json!({}));
+    body["image_base64"] = json!(STANDARD.encode(art()));
+    let (status, _) = post(&app, "/overlay", &body).await;
+    assert_eq!(status, StatusCode::OK);
+
+    body["image_base64"] = json!(format!("data:image/png;base64,{}", STANDARD.encode(art())));
+    let (status, _) = post(&app, "/overlay", &body).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn image_source_must_be_given_exactly_once() {
+    let app = app_with_settings(Settings::default());
+    let (status, _) = post(&app, "/overlay", &cover_params(json!({}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let mut body = cover_params(json!({}));
+    body["image_url"] = json!("https://replicate.delivery/out-0.png");
+    body["image_base64"] = json!(STANDARD.encode(art()));
+    let (status, _) = post(&app, "/overlay", &body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let response = app.oneshot(multipart(None, Some(&art()))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn uploads_must_be_images_within_the_limit() {
+    let app = app_with_settings(Settings::default());
+    let response = app
+        .oneshot(multipart(
+            Some(&cover_params(json!({}))),
+            Some(b"plain text"),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let mut body = cover_params(json!({}));
+    body["image_base64"] = json!("not base64!");
+    let app = app_with_settings(Settings::default());
+    let (status, _) = post(&app, "/overlay", &body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let app = app_with_settings(Settings {
+        upload_max_bytes: 64,
+        ..Default::default()
+    });
+    let response = app
+        .oneshot(multipart(Some(&cover_params(json!({}))), Some(&art())))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
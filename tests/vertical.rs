@@ -0,0 +1,83 @@
+mod common;
+
+use axum::Router;
+use image::{DynamicImage, GenericImageView};
+use litcovers_api::{
+    overlay::{image::WritingMode, mask::Mask},
+    router::app_with_settings,
+    settings::Settings,
+};
+use rusttype::{point, Scale};
+use serde_json::json;
+
+#[test]
+fn stacked_glyphs_run_down_a_centered_column() {
+    let chain = common::font("Stig.ttf").with_writing_mode(WritingMode::Stacked);
+    let scale = Scale::uniform(40.0);
+    let glyphs = chain.layout("AIW", scale, point(100.0, 0.0));
+    assert_eq!(glyphs.len(), 3);
+
+    for pair in glyphs.windows(2) {
+        assert!(pair[1].position().y > pair[0].position().y);
+    }
+    for glyph in &glyphs {
+        let center = glyph.position().x + glyph.unpositioned().h_metrics().advance_width / 2.0;
+        assert!((center - 100.0).abs() < 1.0, "{}", center);
+    }
+    // the column is as long as the glyphs stacked in it
+    let last = glyphs.last().unwrap().position().y;
+    let length = chain.advance_width("AIW", scale);
+    assert!(length > last && length < last + 40.0, "{} {}", length, last);
+}
+
+#[test]
+fn turned_masks_land_back_on_the_image() {
+    // two pixels wide, one high, at (3, 5) on the turned canvas
+    let mut mask = Mask::new(3, 5, 2, 1);
+    mask.data = vec![0.25, 1.0];
+    let turned = mask.turn_clockwise(20);
+    assert_eq!(
+        (turned.left, turned.top, turned.width, turned.height),
+        (14, 3, 1, 2)
+    );
+    assert_eq!(turned.data, vec![0.25, 1.0]);
+}
+
+// draws the title in a spine strip along the right edge
+async fn spine(app: &Router, writing_mode: &str) -> DynamicImage {
+    let body = common::cover(json!({
+        "author": "A",
+        "title": "THE LONG SPINE",
+        "title_position": {"Custom": {"x": 0.8, "y": 0.1, "width": 0.2, "height": 0.8}},
+        "title_writing_mode": writing_mode
+    }));
+    common::render(app, &body).await
+}
+
+// width and height of the light pixels right of the author line
+fn ink_box(image: &DynamicImage) -> (u32, u32) {
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, pixel) in image.pixels() {
+        if x >= 180 && pixel[0] > 128 {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+    assert!(min_x >= 200, "ink outside the strip at x {}", min_x);
+    assert!(min_y >= 38 && max_y < 346, "ink outside the strip");
+    (max_x - min_x + 1, max_y - min_y + 1)
+}
+
+#[tokio::test]
+async fn vertical_titles_fill_a_spine_strip() {
+    let app = app_with_settings(Settings::default());
+    let (width, height) = ink_box(&spine(&app, "Horizontal").await);
+    assert!(width > height, "{}x{}", width, height);
+
+    for mode in ["Stacked", "Rotated"] {
+        let (width, height) = ink_box(&spine(&app, mode).await);
+        assert!(height > 2 * width, "{}: {}x{}", mode, width, height);
+    }
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::font;
+use litcovers_api::overlay::{
+    chain::FontChain,
+    wrap::{wrap_balanced, MeasuredText},
+};
+use rusttype::Scale;
+
+fn widths(lines: &[String], font: &FontChain) -> Vec<f32> {
+    lines
+        .iter()
+        .map(|line| font.advance_width(line, Scale::uniform(1.0)))
+        .collect()
+}
+
+#[test]
+fn lines_are_balanced_by_measured_width() {
+    for name in ["Garet-Heavy.ttf", "Stig.ttf"] {
+        let font = font(name);
+        for (title, length) in [
+            ("A Song of Ice and Fire", 16),
+            ("The Lord of the Rings: The Fellowship of the Ring", 16),
+            ("WWWW iiii WWWW iiii", 10),
+        ] {
+            let lines = wrap_balanced(title, &font, length, true);
+            assert!(lines.len() > 1, "{:?}", lines);
+            assert_eq!(lines.join(" "), title);
+            let widths = widths(&lines, &font);
+            let widest = widths.iter().cloned().fold(0.0, f32::max);
+            let narrowest = widths.iter().cloned().fold(f32::MAX, f32::min);
+            assert!(narrowest > widest * 0.5, "{}: {:?}", name, lines);
+        }
+    }
+}
+
+#[test]
+fn short_last_words_are_not_left_alone() {
+    let font = font("Garet-Heavy.ttf");
+    let lines = wrap_balanced("Harry Potter and the Philosopher's Stone", &font, 16, true);
+    assert!(lines.last().unwrap().contains(' '), "{:?}", lines);
+    // a character count wrap leaves "Stone" on its own
+    let naive = textwrap::wrap("Harry Potter and the Philosopher's Stone", 16);
+    assert_eq!(naive.last().unwrap(), "Stone");
+}
+
+#[test]
+fn only_words_wider_than_the_measure_are_hyphenated() {
+    let font = font("Stig.ttf");
+    let lines = wrap_balanced("Extraordinary Unbelievable Adventures", &font, 8, true);
+    assert!(lines.iter().any(|line| line.ends_with('-')), "{:?}", lines);
+    assert_eq!(
+        lines.concat().replace('-', ""),
+        "ExtraordinaryUnbelievableAdventures"
+    );
+
+    let lines = wrap_balanced("ДОСТОПРИМЕЧАТЕЛЬНОСТИ МОСКВЫ", &font, 10, true);
+    assert!(lines.len() > 2, "{:?}", lines);
+    assert!(lines[0].ends_with('-'), "{:?}", lines);
+    let unbroken = wrap_balanced("ДОСТОПРИМЕЧАТЕЛЬНОСТИ МОСКВЫ", &font, 10, false);
+    assert_eq!(unbroken, vec!["ДОСТОПРИМЕЧАТЕЛЬНОСТИ", "МОСКВЫ"]);
+
+    let lines = wrap_balanced("Harry Potter and the Philosopher's Stone", &font, 16, true);
+    assert!(lines.iter().all(|line| !line.ends_with('-')), "{:?}", lines);
+}
+
+#[test]
+fn hyphenated_words_break_after_their_hyphen() {
+    let font = font("Stig.ttf");
+    let lines = wrap_balanced("Mill-on-the-Floss river story", &font, 10, false);
+    assert_eq!(lines.concat(), "Mill-on-the-Flossriverstory");
+    assert!(lines[0].ends_with('-') && !lines[0].ends_with("--"));
+}
+
+#[test]
+fn measured_text_wraps_like_wrap_balanced() {
+    let font = font("Stig.ttf");
+    let title = "The Lord of the Rings: The Fellowship of the Ring\nBook One";
+    let measured = MeasuredText::new(title, &font);
+    for length in [1, 4, 10, 16, 40, 200] {
+        assert_eq!(
+            measured.wrap(length),
+            wrap_balanced(title, &font, length, false)
+        );
+    }
+}